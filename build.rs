@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 
 fn main() {
@@ -38,4 +40,444 @@ fn main() {
     .unwrap();
     println!("cargo:rerun-if-changed=ld/{}.x", chip);
     println!("cargo:rustc-link-arg=-Tld/{}.x", chip);
+
+    let script = fs::read_to_string(format!("ld/{}.x", chip)).unwrap();
+    let loader_script = fs::read_to_string("ld/loader.x").unwrap();
+
+    println!("cargo:rerun-if-changed=src");
+
+    let mut errors = check_memory_layout(chip, &script)
+        .into_iter()
+        .chain(check_rom_symbols(chip, &script, &loader_script))
+        .map(|error| format!("ld/{chip}.x: {error}"))
+        .collect::<Vec<_>>();
+    errors.extend(check_extern_symbols());
+    if !errors.is_empty() {
+        for error in errors {
+            eprintln!("error: {error}");
+        }
+        std::process::exit(1);
+    }
+}
+
+/// The RAM the loader's bss, data and stack need. See the README.
+const MIN_RWDATA_LENGTH: u64 = 96 * 1024;
+
+struct Region {
+    name: String,
+    start: u64,
+    end: u64,
+}
+
+/// Checks the `MEMORY` block of a chip's linker script. Mistakes here don't fail the link, the
+/// loader just hangs on the target. Whether the sections fit is checked by `loader.x`.
+fn check_memory_layout(chip: &str, script: &str) -> Vec<String> {
+    let regions = match parse_memory(script) {
+        Ok(regions) => regions,
+        Err(error) => return vec![error],
+    };
+
+    let mut errors = Vec::new();
+
+    let region = |name: &str| regions.iter().find(|region| region.name == name);
+    let (Some(iram), Some(rwdata)) = (region("IRAM"), region("RWDATA")) else {
+        return vec!["MEMORY must define both IRAM and RWDATA".to_string()];
+    };
+
+    // The loader's memory, with IRAM where the data bus sees it on chips that have separate
+    // buses. RWDATA is given on the data bus.
+    let mut loader_regions = vec![iram];
+    let iram_data = data_bus_alias(chip, iram);
+    loader_regions.extend(iram_data.as_ref());
+    loader_regions.push(rwdata);
+
+    for iram in [Some(iram), iram_data.as_ref()].iter().flatten() {
+        if overlaps(iram, rwdata.start, rwdata.end) {
+            errors.push(format!(
+                "{} ({:#X}..{:#X}) and RWDATA ({:#X}..{:#X}) overlap",
+                iram.name, iram.start, iram.end, rwdata.start, rwdata.end
+            ));
+        }
+    }
+
+    if rwdata.end - rwdata.start < MIN_RWDATA_LENGTH {
+        errors.push(format!(
+            "RWDATA is {} bytes long, it must be at least {} bytes",
+            rwdata.end - rwdata.start,
+            MIN_RWDATA_LENGTH
+        ));
+    }
+
+    // `ROM_DATA_TABLES` point at tables in ROM, the RAM they initialize holds the ROM's variables.
+    // Their addresses are the ones the linker script assigns outside of ROM.
+    for &region in &loader_regions {
+        let mut overlapping = symbol_addresses(script)
+            .filter(|(_, address)| (region.start..region.end).contains(address))
+            .collect::<Vec<_>>();
+        overlapping.sort_by_key(|&(_, address)| address);
+
+        if let (Some((first, first_address)), Some((last, last_address))) =
+            (overlapping.first(), overlapping.last())
+        {
+            errors.push(format!(
+                "{} ({:#X}..{:#X}) overlaps {} ROM variables, `{}` at {:#X} to `{}` at {:#X}",
+                region.name,
+                region.start,
+                region.end,
+                overlapping.len(),
+                first,
+                first_address,
+                last,
+                last_address
+            ));
+        }
+    }
+
+    errors
+}
+
+fn overlaps(region: &Region, start: u64, end: u64) -> bool {
+    region.start < end && start < region.end
+}
+
+/// Where the data bus sees `iram`, on the chips that reach their internal SRAM through separate
+/// instruction and data bus addresses. `None` if both buses use the same addresses, or if that
+/// part of the SRAM isn't on the data bus.
+fn data_bus_alias(chip: &str, iram: &Region) -> Option<Region> {
+    // The instruction bus range, and how far below it the data bus sees the same SRAM.
+    let (bus, offset) = match chip {
+        "esp32" => {
+            // SRAM1, in reverse order on the data bus: the first word of IRAM is the last word of
+            // 0x3FFE0000..0x40000000.
+            const SUM: u64 = 0x3FFE_0000 + 0x400C_0000;
+            return (iram.start >= 0x400A_0000 && iram.end <= 0x400C_0000).then(|| Region {
+                name: "IRAM on the data bus".to_string(),
+                start: SUM - iram.end,
+                end: SUM - iram.start,
+            });
+        }
+        "esp32s2" => (0x4002_0000..0x4007_0000, 0x7_0000),
+        "esp32s3" => (0x4037_8000..0x403E_0000, 0x6F_0000),
+        "esp32c2" => (0x4038_0000..0x403C_0000, 0x6E_0000),
+        "esp32c3" => (0x4038_0000..0x403E_0000, 0x70_0000),
+        _ => return None,
+    };
+
+    (iram.start >= bus.start && iram.end <= bus.end).then(|| Region {
+        name: "IRAM on the data bus".to_string(),
+        start: iram.start - offset,
+        end: iram.end - offset,
+    })
+}
+
+fn parse_memory(script: &str) -> Result<Vec<Region>, String> {
+    let start = script
+        .find("MEMORY")
+        .ok_or_else(|| "no MEMORY block".to_string())?;
+    let block = &script[start..];
+    let block = &block[block.find('{').ok_or("MEMORY block not opened")? + 1..];
+    let block = &block[..block.find('}').ok_or("MEMORY block not closed")?];
+
+    let mut regions = Vec::new();
+    for line in strip_comments(block).lines() {
+        let Some((name, attributes)) = line.split_once(':') else {
+            continue;
+        };
+
+        let mut origin = None;
+        let mut length = None;
+        for attribute in attributes.split(',') {
+            let Some((key, value)) = attribute.split_once('=') else {
+                continue;
+            };
+            let value = eval(value).ok_or_else(|| format!("can't evaluate `{}`", value.trim()))?;
+            match key.trim() {
+                "ORIGIN" | "org" | "o" => origin = Some(value),
+                "LENGTH" | "len" | "l" => length = Some(value),
+                _ => {}
+            }
+        }
+
+        let name = name.trim();
+        match (origin, length) {
+            (Some(start), Some(length)) => regions.push(Region {
+                name: name.to_string(),
+                start,
+                end: start + length,
+            }),
+            _ => return Err(format!("region {name} needs an ORIGIN and a LENGTH")),
+        }
+    }
+
+    Ok(regions)
+}
+
+/// Returns the `symbol = address;` and `PROVIDE(symbol = address);` assignments.
+fn symbol_addresses(script: &str) -> impl Iterator<Item = (&str, u64)> {
+    assignments(script).filter_map(|(symbol, value)| Some((symbol, eval(value)?)))
+}
+
+/// Returns the `symbol = value;` and `PROVIDE(symbol = value);` assignments.
+fn assignments(script: &str) -> impl Iterator<Item = (&str, &str)> {
+    script.lines().filter_map(|line| {
+        let line = line.trim();
+        let line = match line.strip_prefix("PROVIDE") {
+            Some(provide) => provide.trim_start().strip_prefix('(')?,
+            None => line,
+        };
+        let (symbol, value) = line.split_once('=')?;
+        let symbol = symbol.trim();
+        if symbol.is_empty() || !symbol.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        let value = value
+            .trim()
+            .trim_end_matches(';')
+            .trim()
+            .trim_end_matches(')');
+        Some((symbol, value.trim()))
+    })
+}
+
+/// Evaluates sums and differences of numbers, like `0x40028000 + 0x4000`.
+fn eval(expression: &str) -> Option<u64> {
+    let mut total: i64 = 0;
+    let mut sign = 1;
+    for token in expression
+        .replace('+', " + ")
+        .replace('-', " - ")
+        .split_whitespace()
+    {
+        match token {
+            "+" => sign = 1,
+            "-" => sign = -1,
+            number => {
+                let (digits, multiplier) = match number.strip_suffix(['K', 'k']) {
+                    Some(digits) => (digits, 1024),
+                    None => match number.strip_suffix(['M', 'm']) {
+                        Some(digits) => (digits, 1024 * 1024),
+                        None => (number, 1),
+                    },
+                };
+                let value = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+                    None => digits.parse::<i64>().ok()?,
+                };
+                total += sign * value * multiplier;
+            }
+        }
+    }
+
+    u64::try_from(total).ok()
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start..]
+            .find("*/")
+            .map_or("", |end| &rest[start + end + 2..]);
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Checks that the linker scripts define every `extern` symbol the chip's code uses. The linker
+/// only reports a bare `undefined symbol`, without hinting at what the chip calls it instead.
+fn check_rom_symbols(chip: &str, script: &str, loader_script: &str) -> Vec<String> {
+    let defined = assignments(script)
+        .chain(assignments(loader_script))
+        .map(|(symbol, _)| symbol)
+        .collect::<HashSet<_>>();
+
+    let mut errors = Vec::new();
+    for symbol in extern_symbols(chip) {
+        if defined.contains(symbol) {
+            continue;
+        }
+
+        let candidates = alias_candidates(symbol, &defined);
+        errors.push(match candidates.first() {
+            Some(candidate) => format!(
+                "{chip} doesn't provide `{symbol}`, candidates: `{}`. Add e.g. `PROVIDE({symbol} = {candidate});`",
+                candidates.join("`, `")
+            ),
+            None => format!("{chip} doesn't provide `{symbol}`, and has no similarly named symbol"),
+        });
+    }
+
+    errors
+}
+
+/// The symbols the `extern` blocks in `src` declare for `chip`. `check_extern_symbols` makes sure
+/// each declared symbol is listed for at least one chip.
+fn extern_symbols(chip: &str) -> Vec<&'static str> {
+    let mut symbols = vec![
+        "_bss_start",
+        "_bss_end",
+        "crc32_le",
+        "esp_rom_spiflash_attach",
+        "esp_rom_spiflash_config_param",
+        "esp_rom_spiflash_erase_block",
+        "esp_rom_spiflash_erase_chip",
+        "esp_rom_spiflash_erase_sector",
+        "esp_rom_spiflash_read",
+        "esp_rom_spiflash_read_user_cmd",
+        "esp_rom_spiflash_write",
+        "ets_sha_disable",
+        "ets_sha_enable",
+        "ets_sha_finish",
+        "ets_sha_init",
+        "ets_sha_update",
+        "tinfl_decompress",
+    ];
+
+    match chip {
+        "esp32" | "esp32s2" | "esp32s3" | "esp32c3" => symbols.push("ets_efuse_get_spiconfig"),
+        _ => symbols.push("ets_efuse_secure_boot_enabled"),
+    }
+
+    if chip == "esp32c2" {
+        symbols.extend([
+            "mbedtls_md5_finish_ret",
+            "mbedtls_md5_starts_ret",
+            "mbedtls_md5_update_ret",
+        ]);
+    } else {
+        symbols.extend(["MD5Final", "MD5Init", "MD5Update"]);
+    }
+
+    // The ESP32 and ESP32-C2 don't check app signatures.
+    if !matches!(chip, "esp32" | "esp32c2") {
+        symbols.extend([
+            "ets_secure_boot_read_key_digests",
+            "ets_secure_boot_verify_signature",
+        ]);
+    }
+
+    // Xtensa
+    if matches!(chip, "esp32" | "esp32s2" | "esp32s3") {
+        symbols.push("_stack_start");
+    }
+
+    // Octal flash
+    if chip == "esp32s3" {
+        symbols.extend([
+            "esp_rom_opiflash_erase_area",
+            "esp_rom_opiflash_erase_block_64k",
+            "esp_rom_opiflash_erase_sector",
+            "esp_rom_opiflash_read",
+            "esp_rom_opiflash_wait_idle",
+            "esp_rom_opiflash_wren",
+            "esp_rom_opiflash_write",
+            "ets_delay_us",
+            "ets_efuse_flash_octal_mode",
+            "rom_spiflash_legacy_funcs",
+        ]);
+    }
+
+    symbols
+}
+
+const CHIPS: [&str; 10] = [
+    "esp32", "esp32s2", "esp32s3", "esp32c2", "esp32c3", "esp32c5", "esp32c6", "esp32c61",
+    "esp32h2", "esp32p4",
+];
+
+/// Checks that `extern_symbols` lists every symbol the `extern` blocks in `src` declare. Which
+/// chips need a symbol depends on `cfg`s this can't evaluate, so it only looks at all chips
+/// together.
+fn check_extern_symbols() -> Vec<String> {
+    let listed = CHIPS
+        .iter()
+        .flat_map(|chip| extern_symbols(chip))
+        .collect::<HashSet<_>>();
+
+    let mut errors = Vec::new();
+    for (path, symbol) in declared_externs(Path::new("src")) {
+        if !listed.contains(symbol.as_str()) {
+            errors.push(format!(
+                "{} declares `{symbol}`, add it to `extern_symbols` in build.rs",
+                path.display()
+            ));
+        }
+    }
+
+    errors
+}
+
+/// Returns the functions and statics declared in the `extern "C" { .. }` blocks of the `.rs`
+/// files under `dir`.
+fn declared_externs(dir: &Path) -> Vec<(PathBuf, String)> {
+    let mut paths = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut symbols = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            symbols.extend(declared_externs(&path));
+            continue;
+        }
+        if path.extension().is_none_or(|extension| extension != "rs") {
+            continue;
+        }
+
+        let source = fs::read_to_string(&path).unwrap();
+        let mut depth = 0;
+        for line in source.lines() {
+            let line = line.split("//").next().unwrap_or("").trim();
+            if depth == 0 {
+                if line.ends_with("extern \"C\" {") {
+                    depth = 1;
+                }
+                continue;
+            }
+
+            if depth == 1 {
+                let line = line.strip_prefix("pub ").unwrap_or(line);
+                let name = match line.strip_prefix("fn ") {
+                    Some(rest) => rest.split('(').next(),
+                    None => line.strip_prefix("static ").map(|rest| {
+                        let rest = rest.strip_prefix("mut ").unwrap_or(rest);
+                        rest.split(':').next().unwrap_or(rest)
+                    }),
+                };
+                if let Some(name) = name {
+                    symbols.push((path.clone(), name.trim().to_string()));
+                }
+            }
+
+            depth += line.matches('{').count();
+            depth = depth.saturating_sub(line.matches('}').count());
+        }
+    }
+
+    symbols
+}
+
+/// Symbols that likely name the same ROM function, like `spi_flash_attach` for
+/// `esp_rom_spiflash_attach`.
+fn alias_candidates<'a>(symbol: &str, defined: &HashSet<&'a str>) -> Vec<&'a str> {
+    fn stem(symbol: &str) -> String {
+        let mut symbol = symbol;
+        for prefix in ["esp_rom_", "ets_", "rom_", "esp_"] {
+            symbol = symbol.strip_prefix(prefix).unwrap_or(symbol);
+        }
+        symbol.replace('_', "")
+    }
+
+    let wanted = stem(symbol);
+    let mut candidates = defined
+        .iter()
+        .copied()
+        .filter(|candidate| stem(candidate).ends_with(&wanted))
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    candidates.truncate(5);
+    candidates
 }
@@ -1,7 +1,8 @@
 MEMORY {
     /* SRAM1 */
     IRAM : ORIGIN = 0x400A0000, LENGTH = 0x8000
-    RWDATA : ORIGIN = 0x3FFE0000, LENGTH = 0x18000
+    /* The end of SRAM2. The start of SRAM1 holds the ROM's data. */
+    RWDATA : ORIGIN = 0x3FFC8000, LENGTH = 0x18000
 }
 
 INCLUDE "loader.x"
@@ -184,6 +185,9 @@ PROVIDE ( lmp_desc_tab = 0x3ff96e6c );
 PROVIDE ( lmp_ext_desc_tab = 0x3ff96d9c );
 PROVIDE ( lm_state = 0x3ffb9a1c );
 PROVIDE ( maxSecretKey_256 = 0x3ff97448 );
+PROVIDE ( MD5Final = 0x4005db1c );
+PROVIDE ( MD5Init = 0x4005da7c );
+PROVIDE ( MD5Update = 0x4005da9c );
 PROVIDE ( mmu_init = 0x400095a4 );
 PROVIDE ( MultiplyBigHexByUint32_256 = 0x40016214 );
 PROVIDE ( MultiplyBigHexModP256 = 0x400160b8 );
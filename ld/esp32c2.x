@@ -1,7 +1,8 @@
 MEMORY {
     /* Start 64k into the RAM region */
     IRAM : ORIGIN = 0x4038C000, LENGTH = 0x10800
-    RWDATA : ORIGIN = 0x3FCB0000, LENGTH = 0x20000
+    /* After IRAM, which the data bus sees at 0x3FCAC000..0x3FCBC800 */
+    RWDATA : ORIGIN = 0x3FCBC800, LENGTH = 0x20000
 }
 
 INCLUDE "loader.x"
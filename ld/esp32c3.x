@@ -86,6 +86,29 @@ PROVIDE( ets_efuse_get_spiconfig = 0x4000071c );
 
 PROVIDE( uart_tx_one_char = 0x40000068 );
 
+/***************************************
+ Group crypto
+ ***************************************/
+
+/* Functions */
+crc32_le = 0x40000628;
+ets_sha_enable = 0x40000644;
+ets_sha_disable = 0x40000648;
+ets_sha_init = 0x40000650;
+ets_sha_update = 0x4000065c;
+ets_sha_finish = 0x40000660;
+MD5Init = 0x40000614;
+MD5Update = 0x40000618;
+MD5Final = 0x4000061c;
+
+/***************************************
+ Group secureboot
+ ***************************************/
+
+/* Functions */
+ets_secure_boot_verify_signature = 0x40000758;
+ets_secure_boot_read_key_digests = 0x4000075c;
+
 /***************************************
  Group miniz
  ***************************************/
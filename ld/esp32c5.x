@@ -1,7 +1,8 @@
 MEMORY {
     /* Start 64k into the RAM region */
     IRAM : ORIGIN = 0x40810000, LENGTH = 0x10000
-    RWDATA : ORIGIN = 0x40840000, LENGTH = 0x20000
+    /* Stops short of the end of RAM, which holds the ROM's data. */
+    RWDATA : ORIGIN = 0x40840000, LENGTH = 0x1C000
 }
 
 INCLUDE "loader.x"
@@ -14,8 +14,19 @@ INCLUDE "loader.x"
  * These are all weak symbols that could be overwritten in ESP-IDF.
  */
 
+PROVIDE ( crc32_le = 0x400119dc );
 PROVIDE ( ets_delay_us = 0x4000d888 );
 PROVIDE ( ets_efuse_get_spiconfig = 0x4000e4a0 );
+PROVIDE ( ets_secure_boot_read_key_digests = 0x400101ac );
+PROVIDE ( ets_secure_boot_verify_signature = 0x400102cc );
+PROVIDE ( ets_sha_disable = 0x400107b4 );
+PROVIDE ( ets_sha_enable = 0x40010788 );
+PROVIDE ( ets_sha_finish = 0x40010ab8 );
+PROVIDE ( ets_sha_init = 0x400107e0 );
+PROVIDE ( ets_sha_update = 0x400109f8 );
+PROVIDE ( MD5Final = 0x4000530c );
+PROVIDE ( MD5Init = 0x4000526c );
+PROVIDE ( MD5Update = 0x4000528c );
 PROVIDE ( s_cdcacm_old_rts = 0x3ffffd34 );
 PROVIDE ( SelectSpiFunction = 0x40015d08 );
 PROVIDE ( SelectSpiQIO = 0x40015b88 );
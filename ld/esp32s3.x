@@ -10,6 +10,29 @@ INCLUDE "loader.x"
 PROVIDE( ets_delay_us = 0x40000600 );
 PROVIDE ( esp_rom_spiflash_attach = spi_flash_attach );
 
+/***************************************
+ Group crypto
+ ***************************************/
+
+/* Functions */
+crc32_le = 0x40001c98;
+ets_sha_enable = 0x40001cec;
+ets_sha_disable = 0x40001cf8;
+ets_sha_init = 0x40001d10;
+ets_sha_update = 0x40001d34;
+ets_sha_finish = 0x40001d40;
+MD5Init = 0x40001c5c;
+MD5Update = 0x40001c68;
+MD5Final = 0x40001c74;
+
+/***************************************
+ Group secureboot
+ ***************************************/
+
+/* Functions */
+ets_secure_boot_verify_signature = 0x40002148;
+ets_secure_boot_read_key_digests = 0x40002154;
+
 /***************************************
  Group miniz
  ***************************************/
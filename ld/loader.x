@@ -1,5 +1,8 @@
 /* Shared loader, requires MEMORY definitions each chip */
 
+/* The stack grows down from the end of RWDATA, towards bss and data. */
+_min_stack_size = 16K;
+
 SECTIONS {
     /* Section for code and readonly data, specified by flashloader standard. */
     PrgCode : {
@@ -52,6 +55,10 @@ SECTIONS {
         *(.data .data.*)
         *(.sdata .sdata.*)
 
+        _data_end = ABSOLUTE(.);
+        ASSERT(ORIGIN(RWDATA) + LENGTH(RWDATA) - _data_end >= _min_stack_size,
+            "RWDATA has no room for the stack after bss (including PAGE_BUFFER) and data");
+
         . = ORIGIN(RWDATA) + LENGTH(RWDATA);
         _stack_start = ABSOLUTE(.);
     } > RWDATA
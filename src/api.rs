@@ -48,3 +48,73 @@ pub unsafe extern "C" fn ReadFlash(adr: u32, sz: u32, buf: *mut u8) -> i32 {
 pub unsafe extern "C" fn FlashSize() -> i32 {
     crate::FlashSize_impl()
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn ReadEfuse(block: u32, sz: u32, buf: *mut u8) -> i32 {
+    crate::ReadEfuse_impl(block, sz, buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ChipInfo(buf: *mut u8) -> i32 {
+    crate::ChipInfo_impl(buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn UnlockBootloader() -> i32 {
+    crate::UnlockBootloader_impl()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn SetProtectedRanges(count: u32, buf: *const u32) -> i32 {
+    crate::SetProtectedRanges_impl(count, buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ReadPartitions(sz: u32, buf: *mut u8) -> i32 {
+    crate::ReadPartitions_impl(sz, buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn PreservePartitions() -> i32 {
+    crate::PreservePartitions_impl()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn SkipBlankErases() -> i32 {
+    crate::SkipBlankErases_impl()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn CheckImage(adr: u32, sz: u32, buf: *const u8) -> i32 {
+    crate::CheckImage_impl(adr, sz, buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn SequencedStreams() -> i32 {
+    crate::SequencedStreams_impl()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn UseNativeFlash() -> i32 {
+    crate::UseNativeFlash_impl()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn SelectOtaSlot(slot: u32) -> i32 {
+    crate::SelectOtaSlot_impl(slot)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn SetFlashParams(mode: u32, size: u32, freq: u32) -> i32 {
+    crate::SetFlashParams_impl(mode, size, freq)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn Sha256Digest(adr: u32, sz: u32, buf: *mut u8) -> i32 {
+    crate::Sha256Digest_impl(adr, sz, buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FindNonBlank(adr: u32, sz: u32, pat: u8) -> i32 {
+    crate::FindNonBlank_impl(adr, sz, pat)
+}
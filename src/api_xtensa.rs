@@ -133,3 +133,178 @@ extern "C" fn FlashSize() {
         "break 1, 15",
     );
 }
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn ReadEfuse() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "mov.n a8, a4",
+        "call4 ReadEfuse_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn ChipInfo() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "call4 ChipInfo_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn UnlockBootloader() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "call4 UnlockBootloader_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn SetProtectedRanges() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "call4 SetProtectedRanges_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn ReadPartitions() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "call4 ReadPartitions_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn PreservePartitions() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "call4 PreservePartitions_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn SkipBlankErases() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "call4 SkipBlankErases_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn CheckImage() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "mov.n a8, a4",
+        "call4 CheckImage_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn SequencedStreams() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "call4 SequencedStreams_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn UseNativeFlash() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "call4 UseNativeFlash_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn SelectOtaSlot() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "call4 SelectOtaSlot_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn SetFlashParams() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "mov.n a8, a4",
+        "call4 SetFlashParams_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn Sha256Digest() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "mov.n a8, a4",
+        "call4 Sha256Digest_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
+
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+extern "C" fn FindNonBlank() {
+    core::arch::naked_asm!(
+        "l32r a1, STACK_PTR",
+        "mov.n a6, a2",
+        "mov.n a7, a3",
+        "mov.n a8, a4",
+        "call4 FindNonBlank_impl",
+        "mov.n a2, a6",
+        "break 1, 15",
+    );
+}
@@ -1,5 +1,5 @@
 use crate::{
-    efuse::{read_field, EfuseInfo},
+    efuse::{read_field, EfuseField, EfuseFields, EfuseInfo},
     flash::MemSpi,
     rom::{RomDataTable, RomDataTables},
 };
@@ -7,6 +7,11 @@ use crate::{
 // Max of 16MB
 pub const MAX_FLASH_SIZE: u32 = 0x1000000;
 
+pub const BOOTLOADER_OFFSET: u32 = 0x1000;
+
+/// `esp_chip_id_t` in the image header.
+pub const IMAGE_CHIP_ID: u16 = 0x0000;
+
 pub const ROM_DATA_TABLES: RomDataTables = &[RomDataTable {
     min_revision: 0,
     data_start: 0x4000D4F8,
@@ -22,6 +27,24 @@ pub const EFUSE_INFO: EfuseInfo = EfuseInfo {
     block_sizes: &[7 + 7, 8, 8, 8],
 };
 
+pub const EFUSE_FIELDS: EfuseFields = EfuseFields {
+    mac: EfuseField::new(0, 32, 48),
+    // CHIP_PACKAGE
+    pkg_version: EfuseField::new(0, 105, 3),
+    flash_type: None,
+    flash_cap: None,
+    // ABS_DONE_0 and ABS_DONE_1, Secure Boot V1 and V2. Either one being set enables it.
+    secure_boot_en: EfuseField::new(0, 196, 2),
+    flash_crypt_cnt: EfuseField::new(0, 20, 7),
+    // JTAG_DISABLE
+    dis_pad_jtag: EfuseField::new(0, 198, 1),
+    // UART_DOWNLOAD_DIS
+    dis_download_mode: EfuseField::new(0, 27, 1),
+    enable_security_download: None,
+    // Anti-rollback on the ESP32 uses a project-defined part of BLK3.
+    secure_version: None,
+};
+
 pub const MEM_SPI: MemSpi = MemSpi {
     base: 0x3ff4_2000,
     cmd: 0x00,
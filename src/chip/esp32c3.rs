@@ -1,5 +1,5 @@
 use crate::{
-    efuse::{read_field, EfuseInfo},
+    efuse::{read_field, EfuseField, EfuseFields, EfuseInfo},
     flash::MemSpi,
     rom::{RomDataTable, RomDataTables},
 };
@@ -7,6 +7,11 @@ use crate::{
 // Max of 16MB
 pub const MAX_FLASH_SIZE: u32 = 0x1000000;
 
+pub const BOOTLOADER_OFFSET: u32 = 0x0;
+
+/// `esp_chip_id_t` in the image header.
+pub const IMAGE_CHIP_ID: u16 = 0x0005;
+
 pub const ROM_DATA_TABLES: RomDataTables = &[
     RomDataTable {
         min_revision: 0,
@@ -38,6 +43,19 @@ pub const EFUSE_INFO: EfuseInfo = EfuseInfo {
     block_sizes: &[6, 6, 8, 8, 8, 8, 8, 8, 8, 8, 8],
 };
 
+pub const EFUSE_FIELDS: EfuseFields = EfuseFields {
+    mac: EfuseField::new(1, 0, 48),
+    pkg_version: EfuseField::new(1, 117, 3),
+    flash_type: Some(EfuseField::new(0, 137, 1)),
+    flash_cap: Some(EfuseField::new(1, 123, 3)),
+    secure_boot_en: EfuseField::new(0, 116, 1),
+    flash_crypt_cnt: EfuseField::new(0, 82, 3),
+    dis_pad_jtag: EfuseField::new(0, 51, 1),
+    dis_download_mode: EfuseField::new(0, 128, 1),
+    enable_security_download: Some(EfuseField::new(0, 133, 1)),
+    secure_version: Some(EfuseField::new(0, 142, 16)),
+};
+
 pub const MEM_SPI: MemSpi = MemSpi {
     base: 0x6000_2000,
     cmd: 0x00,
@@ -0,0 +1,50 @@
+/// Device identity reported by the `ChipInfo` custom function.
+///
+/// The layout is shared with the host. New fields must only be appended, bumping `version`.
+#[repr(C)]
+pub struct ChipInfo {
+    pub version: u32,
+    /// NUL-padded chip name, e.g. `esp32c3`
+    pub chip_name: [u8; 16],
+    /// Manufacturer ID in the lowest byte, followed by the two device ID bytes
+    pub jedec_id: u32,
+    /// Detected flash size in bytes, 0 if unknown
+    pub flash_size: u32,
+    pub mac: [u8; 6],
+    pub major_revision: u8,
+    pub minor_revision: u8,
+    pub pkg_version: u8,
+    pub secure_boot: u8,
+    pub flash_encryption: u8,
+    _reserved: u8,
+}
+
+impl ChipInfo {
+    const VERSION: u32 = 1;
+
+    /// Fills the structure in place. The host buffer is written field by field, so that we don't
+    /// need a `memcpy`.
+    pub fn fill(&mut self) {
+        let revision = crate::efuse::read_chip_revision();
+
+        self.version = Self::VERSION;
+
+        let mut name = env!("CHIP_NAME").as_bytes().iter();
+        for dst in self.chip_name.iter_mut() {
+            *dst = match name.next() {
+                Some(src) => unsafe { crate::chip::read_via_data_bus(src) },
+                None => 0,
+            };
+        }
+
+        self.jedec_id = crate::flash::read_jedec_id();
+        self.flash_size = crate::flash::get_flash_size().max(0) as u32;
+        crate::efuse::read_mac(&mut self.mac);
+        self.major_revision = (revision / 100) as u8;
+        self.minor_revision = (revision % 100) as u8;
+        self.pkg_version = crate::chip::EFUSE_FIELDS.pkg_version.read() as u8;
+        self.secure_boot = crate::efuse::secure_boot_enabled() as u8;
+        self.flash_encryption = crate::efuse::flash_encryption_enabled() as u8;
+        self._reserved = 0;
+    }
+}
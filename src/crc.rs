@@ -0,0 +1,7 @@
+pub fn crc32_le(crc: u32, data: &[u8]) -> u32 {
+    extern "C" {
+        fn crc32_le(crc: u32, buf: *const u8, len: u32) -> u32;
+    }
+
+    unsafe { crc32_le(crc, data.as_ptr(), data.len() as u32) }
+}
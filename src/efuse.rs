@@ -4,21 +4,169 @@ pub struct EfuseInfo {
     pub block_sizes: &'static [u32],
 }
 
+/// A field in the eFuse memory, using the block/bit coordinates of ESP-IDF's
+/// `esp_efuse_table.csv`.
+#[derive(Clone, Copy)]
+pub struct EfuseField {
+    pub block: u32,
+    pub bit_start: u32,
+    pub bit_count: u32,
+}
+
+impl EfuseField {
+    pub const fn new(block: u32, bit_start: u32, bit_count: u32) -> Self {
+        Self {
+            block,
+            bit_start,
+            bit_count,
+        }
+    }
+
+    fn read_word(&self, word: u32) -> u32 {
+        let info = crate::chip::EFUSE_INFO;
+
+        let block_offset = info
+            .block_sizes
+            .iter()
+            .take(self.block as usize)
+            .sum::<u32>();
+        let address = info.block0 + block_offset * 4 + word * 4;
+
+        unsafe { core::ptr::read_volatile(address as *const u32) }
+    }
+
+    /// Reads up to 32 bits starting at `bit_start`, which may straddle a word boundary.
+    fn read_bits(&self, bit_start: u32, bit_count: u32) -> u32 {
+        let word = bit_start / 32;
+        let shift = bit_start % 32;
+
+        let mut value = self.read_word(word) >> shift;
+        if shift + bit_count > 32 {
+            value |= self.read_word(word + 1) << (32 - shift);
+        }
+
+        if bit_count < 32 {
+            value & ((1 << bit_count) - 1)
+        } else {
+            value
+        }
+    }
+
+    /// Reads the field as an integer. Fields wider than 32 bits are truncated, use
+    /// [`EfuseField::read_bytes`] for those.
+    pub fn read(&self) -> u32 {
+        self.read_bits(self.bit_start, self.bit_count.min(32))
+    }
+
+    /// Reads the field into `buf`, least significant byte first. Bytes past the end of the field
+    /// are set to 0.
+    pub fn read_bytes(&self, buf: &mut [u8]) {
+        for (idx, byte) in buf.iter_mut().enumerate() {
+            let offset = idx as u32 * 8;
+            *byte = if offset < self.bit_count {
+                self.read_bits(self.bit_start + offset, (self.bit_count - offset).min(8)) as u8
+            } else {
+                0
+            };
+        }
+    }
+}
+
+/// Named eFuse fields the flash loader may need to know about. Fields that don't exist on a chip
+/// are `None`.
+#[allow(unused)]
+pub struct EfuseFields {
+    /// Factory MAC address, 48 bits, stored last byte first.
+    pub mac: EfuseField,
+    pub pkg_version: EfuseField,
+    pub flash_type: Option<EfuseField>,
+    pub flash_cap: Option<EfuseField>,
+    pub secure_boot_en: EfuseField,
+    /// Flash encryption is enabled if an odd number of bits are set.
+    pub flash_crypt_cnt: EfuseField,
+    pub dis_pad_jtag: EfuseField,
+    pub dis_download_mode: EfuseField,
+    pub enable_security_download: Option<EfuseField>,
+    /// Anti-rollback counter, the minimum app `secure_version` is the number of bits set.
+    pub secure_version: Option<EfuseField>,
+}
+
 pub fn read_field<const BLOCK: usize, const BIT_START: u32, const BIT_COUNT: u32>() -> u8 {
+    const { assert!(BIT_COUNT <= 8, "use EfuseField::read for wider fields") };
+
+    EfuseField::new(BLOCK as u32, BIT_START, BIT_COUNT).read() as u8
+}
+
+pub fn read_mac(mac: &mut [u8; 6]) {
+    crate::chip::EFUSE_FIELDS.mac.read_bytes(mac);
+    mac.reverse();
+}
+
+/// Copies the raw words of an eFuse block into `buf`.
+pub fn read_block(block: u32, buf: &mut [u8]) -> i32 {
     let info = crate::chip::EFUSE_INFO;
 
-    let word_offset = BIT_START / 32;
-    let bit_offset = BIT_START % 32;
+    let Some(&block_size) = info.block_sizes.get(block as usize) else {
+        return -1;
+    };
+    if buf.len() > block_size as usize * 4 {
+        return -2;
+    }
+
+    let block_offset = info.block_sizes.iter().take(block as usize).sum::<u32>();
+    let mut address = info.block0 + block_offset * 4;
+
+    for chunk in buf.chunks_mut(4) {
+        let word = unsafe { core::ptr::read_volatile(address as *const u32) };
+        for (dst, src) in chunk.iter_mut().zip(word.to_le_bytes()) {
+            *dst = src;
+        }
+        address += 4;
+    }
 
-    let mask = (1 << BIT_COUNT) - 1;
-    let bit_mask = mask << bit_offset;
+    0
+}
+
+#[cfg(any(
+    feature = "esp32c2",
+    feature = "esp32c5",
+    feature = "esp32c6",
+    feature = "esp32c61",
+    feature = "esp32h2",
+    feature = "esp32p4"
+))]
+pub fn secure_boot_enabled() -> bool {
+    extern "C" {
+        fn ets_efuse_secure_boot_enabled() -> bool;
+    }
+
+    unsafe { ets_efuse_secure_boot_enabled() }
+}
 
-    let block_offset = info.block_sizes.iter().take(BLOCK).sum::<u32>();
-    let address = info.block0 + block_offset * 4 + word_offset * 4;
-    let value =
-        (unsafe { core::ptr::read_volatile(address as *const u32) } & bit_mask) >> bit_offset;
+#[cfg(any(
+    feature = "esp32",
+    feature = "esp32s2",
+    feature = "esp32s3",
+    feature = "esp32c3",
+))]
+pub fn secure_boot_enabled() -> bool {
+    crate::chip::EFUSE_FIELDS.secure_boot_en.read() != 0
+}
+
+pub fn flash_encryption_enabled() -> bool {
+    crate::chip::EFUSE_FIELDS
+        .flash_crypt_cnt
+        .read()
+        .count_ones()
+        % 2
+        == 1
+}
 
-    value as u8
+/// The lowest app `secure_version` the bootloader will boot, 0 if anti-rollback is not in use.
+pub fn secure_version() -> u32 {
+    crate::chip::EFUSE_FIELDS
+        .secure_version
+        .map_or(0, |field| field.read().count_ones())
 }
 
 pub fn read_chip_revision() -> u32 {
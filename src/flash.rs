@@ -1,28 +1,4 @@
 extern "C" {
-
-    // fn esp_rom_spiflash_write_encrypted_enable();
-    // fn esp_rom_spiflash_write_encrypted_disable();
-    // fn esp_rom_spiflash_write_encrypted(addr: u32, data: *const u8, len: u32);
-    // fn esp_rom_spiflash_select_qio_pins();
-    // fn esp_rom_spi_flash_auto_sus_res();
-    // fn esp_rom_spi_flash_send_resume();
-    // fn esp_rom_spi_flash_update_id();
-    // fn esp_rom_spiflash_config_clk();
-    // fn esp_rom_spiflash_config_readmode();
-    // fn esp_rom_spiflash_read_status(/* esp_rom_spiflash_chip_t *spi ,*/ status: *mut u32);
-    // fn esp_rom_spiflash_read_statushigh(/* esp_rom_spiflash_chip_t *spi ,*/ status: *mut u32);
-    // fn esp_rom_spiflash_write_status(/* esp_rom_spiflash_chip_t *spi ,*/ status: *mut u32);
-
-    fn esp_rom_spiflash_erase_chip() -> i32;
-    fn esp_rom_spiflash_erase_block(block_number: u32) -> i32;
-    // fn esp_rom_spiflash_erase_sector(sector_number: u32) -> i32;
-    /// address (4 byte alignment), data, length
-    fn esp_rom_spiflash_write(dest_addr: u32, data: *const u8, len: u32) -> i32;
-    /// address (4 byte alignment), data, length
-    fn esp_rom_spiflash_read(src_addr: u32, data: *mut u8, len: u32) -> i32;
-    fn esp_rom_spiflash_read_user_cmd(status: *mut u32, cmd: u8) -> i32;
-    // fn esp_rom_spiflash_unlock() -> i32;
-    // fn esp_rom_spiflash_lock(); // can't find in idf defs?
     fn esp_rom_spiflash_attach(config: u32, legacy: bool);
 
     fn esp_rom_spiflash_config_param(
@@ -43,55 +19,46 @@ extern "C" {
     fn ets_efuse_get_spiconfig() -> u32;
 }
 
+mod native;
 #[cfg(feature = "esp32s3")]
-#[allow(non_camel_case_types)]
-mod s3 {
-    type spi_flash_func_t = unsafe extern "C" fn();
-    type spi_flash_op_t = unsafe extern "C" fn() -> i32;
-    type spi_flash_erase_t = unsafe extern "C" fn(u32) -> i32;
-    type spi_flash_rd_t = unsafe extern "C" fn(u32, *mut (), i32) -> i32;
-    type spi_flash_wr_t = unsafe extern "C" fn(u32, *const u32, i32) -> i32;
-    type spi_flash_ewr_t = unsafe extern "C" fn(u32, *const (), u32) -> i32;
-    type spi_flash_wren_t = unsafe extern "C" fn(*mut ()) -> i32;
-    type spi_flash_erase_area_t = unsafe extern "C" fn(u32, u32) -> i32;
-
-    #[repr(C)]
-    pub struct spiflash_legacy_funcs_t {
-        pub pp_addr_bit_len: u8,
-        pub se_addr_bit_len: u8,
-        pub be_addr_bit_len: u8,
-        pub rd_addr_bit_len: u8,
-        pub read_sub_len: u32,
-        pub write_sub_len: u32,
-        pub unlock: Option<spi_flash_op_t>,
-        pub erase_sector: Option<spi_flash_erase_t>,
-        pub erase_block: Option<spi_flash_erase_t>,
-        pub read: Option<spi_flash_rd_t>,
-        pub write: Option<spi_flash_wr_t>,
-        pub encrypt_write: Option<spi_flash_ewr_t>,
-        pub check_sus: Option<spi_flash_func_t>,
-        pub wren: Option<spi_flash_wren_t>,
-        pub wait_idle: Option<spi_flash_op_t>,
-        pub erase_area: Option<spi_flash_erase_area_t>,
+mod octal;
+mod rom;
+
+/// Flash operations, either through the ROM functions or by driving the SPI controller directly.
+/// Selected at `attach`, or by `use_native`.
+pub trait FlashBackend {
+    fn erase_sector(&self, address: u32) -> i32;
+    fn erase_block(&self, address: u32) -> i32;
+    fn erase_chip(&self) -> i32;
+    fn write(&self, address: u32, data: &[u8]) -> i32;
+    fn read(&self, address: u32, data: &mut [u8]) -> i32;
+
+    fn read_id(&self) -> u32 {
+        const RDID: u32 = 0x9F;
+        spi_send_command(RDID, 24)
     }
-}
 
-#[cfg(feature = "esp32s3")]
-use s3::*;
+    fn read_status(&self) -> Result<u32, i32> {
+        rom::read_status()
+    }
 
-#[cfg(feature = "esp32s3")]
-extern "C" {
-    static mut rom_spiflash_legacy_funcs: *const spiflash_legacy_funcs_t;
+    fn wait_idle(&self) -> i32 {
+        const SR_WIP: u32 = 1 << 0;
 
-    fn ets_efuse_flash_octal_mode() -> bool;
+        loop {
+            match self.read_status() {
+                Ok(status) if status & SR_WIP != 0 => {}
+                Ok(_) => return 0,
+                Err(res) => return res,
+            }
+        }
+    }
+}
 
-    fn esp_rom_opiflash_wait_idle() -> i32;
-    fn esp_rom_opiflash_erase_block_64k(addr: u32) -> i32;
-    fn esp_rom_opiflash_erase_sector(addr: u32) -> i32;
-    fn esp_rom_opiflash_read(addr: u32, buf: *mut (), len: i32) -> i32;
-    fn esp_rom_opiflash_write(addr: u32, data: *const u32, len: i32) -> i32;
-    fn esp_rom_opiflash_wren(p: *mut ()) -> i32;
-    fn esp_rom_opiflash_erase_area(start_addr: u32, end_addr: u32) -> i32;
+static mut BACKEND: &dyn FlashBackend = &rom::RomFlash;
+
+fn backend() -> &'static dyn FlashBackend {
+    unsafe { BACKEND }
 }
 
 pub fn attach() -> i32 {
@@ -115,10 +82,11 @@ pub fn attach() -> i32 {
 
     unsafe { esp_rom_spiflash_attach(spiconfig, false) };
 
+    // Octal flash needs the ROM's function table patched before it's configured.
     #[cfg(feature = "esp32s3")]
-    if unsafe { ets_efuse_flash_octal_mode() } {
-        init_ospi_funcs();
-    }
+    let backend = octal::attach().unwrap_or(&rom::RomFlash);
+    #[cfg(not(feature = "esp32s3"))]
+    let backend: &dyn FlashBackend = &rom::RomFlash;
 
     let config_result = unsafe {
         esp_rom_spiflash_config_param(
@@ -131,51 +99,111 @@ pub fn attach() -> i32 {
         )
     };
 
-    if config_result == 0 {
-        0
-    } else {
-        -1
+    if config_result != 0 {
+        return -1;
     }
+
+    unsafe { BACKEND = backend };
+
+    0
+}
+
+/// Switches to the native SPI1 driver until the next `attach`. Returns false, leaving the ROM
+/// functions in use, if it can't be used with this chip or flash.
+pub fn use_native() -> bool {
+    // Octal flash needs the ROM's OPI functions.
+    #[cfg(feature = "esp32s3")]
+    if octal::enabled() {
+        return false;
+    }
+
+    let Some(backend) = native::attach() else {
+        return false;
+    };
+    unsafe { BACKEND = backend };
+
+    true
 }
 
 pub fn erase_block(adr: u32) -> i32 {
     crate::dprintln!("ERASE @ {}", adr);
 
-    unsafe { esp_rom_spiflash_erase_block(adr / crate::properties::FLASH_BLOCK_SIZE) }
+    backend().erase_block(adr)
+}
+
+pub fn erase_sector(adr: u32) -> i32 {
+    crate::dprintln!("ERASE SECTOR @ {}", adr);
+
+    backend().erase_sector(adr)
 }
 
 pub fn erase_chip() -> i32 {
-    unsafe { esp_rom_spiflash_erase_chip() }
+    backend().erase_chip()
 }
 
 pub fn write_flash(address: u32, data: &[u8]) -> i32 {
     if data.is_empty() {
         return 0;
     }
-    let len = data.len() as u32;
-    unsafe { esp_rom_spiflash_write(address, data.as_ptr(), len) }
+
+    backend().write(address, data)
 }
 
 pub fn read_flash(address: u32, data: &mut [u8]) -> i32 {
     if data.is_empty() {
         return 0;
     }
-    let len = data.len() as u32;
-    unsafe { esp_rom_spiflash_read(address, data.as_mut_ptr(), len) }
+
+    backend().read(address, data)
 }
 
-pub fn wait_for_idle() -> i32 {
-    const SR_WIP: u32 = 1 << 0;
+/// Returns the address of the first byte in `address..address + len` that isn't `pattern`.
+pub fn find_non_blank(address: u32, len: u32, pattern: u8) -> Result<Option<u32>, i32> {
+    const CHUNK_WORDS: usize = 256;
+
+    let pattern = u32::from(pattern) * 0x0101_0101;
+    let end = address + len;
+    let mut buf = core::mem::MaybeUninit::<[u32; CHUNK_WORDS]>::uninit();
+
+    // Compare whole words, masking off the bytes outside of the range.
+    let mut word_address = address & !3;
+    while word_address < end {
+        let chunk_len = (end - word_address)
+            .next_multiple_of(4)
+            .min(CHUNK_WORDS as u32 * 4);
+
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(buf.as_mut_ptr().cast::<u8>(), chunk_len as usize)
+        };
+        let status = read_flash(word_address, bytes);
+        if status != 0 {
+            return Err(status);
+        }
 
-    let mut status = SR_WIP;
-    while status & SR_WIP != 0 {
-        let res = unsafe { esp_rom_spiflash_read_user_cmd(&mut status, 0x05) };
-        if res != 0 {
-            return res;
+        let words = unsafe {
+            core::slice::from_raw_parts(buf.as_ptr().cast::<u32>(), chunk_len as usize / 4)
+        };
+        for word in words {
+            let mut diff = word ^ pattern;
+            if word_address < address {
+                diff &= u32::MAX << ((address - word_address) * 8);
+            }
+            if end - word_address < 4 {
+                diff &= u32::MAX >> ((4 - (end - word_address)) * 8);
+            }
+            if diff != 0 {
+                return Ok(Some(word_address + diff.trailing_zeros() / 8));
+            }
+
+            word_address += 4;
         }
     }
 
-    0
+    Ok(None)
+}
+
+pub fn wait_for_idle() -> i32 {
+    backend().wait_idle()
 }
 
 fn read_spi_reg(reg: u32) -> u32 {
@@ -275,9 +303,12 @@ fn spi_send_command(command: u32, len: u32) -> u32 {
     value & ((1 << len) - 1)
 }
 
+pub fn read_jedec_id() -> u32 {
+    backend().read_id()
+}
+
 pub fn get_flash_size() -> i32 {
-    const RDID: u32 = 0x9F;
-    let id = spi_send_command(RDID, 24);
+    let id = read_jedec_id();
 
     const KB: i32 = 1024;
     const MB: i32 = 1024 * KB;
@@ -326,31 +357,3 @@ pub fn get_flash_size() -> i32 {
         }
     }
 }
-
-#[cfg(feature = "esp32s3")]
-fn init_ospi_funcs() {
-    static FUNCS: spiflash_legacy_funcs_t = spiflash_legacy_funcs_t {
-        pp_addr_bit_len: 24,
-        se_addr_bit_len: 24,
-        be_addr_bit_len: 24,
-        rd_addr_bit_len: 24,
-        read_sub_len: 16,
-        write_sub_len: 32,
-        unlock: Some(esp_rom_opiflash_wait_idle),
-        erase_block: Some(esp_rom_opiflash_erase_block_64k),
-        erase_sector: Some(esp_rom_opiflash_erase_sector),
-        read: Some(esp_rom_opiflash_read),
-        write: Some(esp_rom_opiflash_write),
-        encrypt_write: None,
-        check_sus: None,
-        wait_idle: Some(esp_rom_opiflash_wait_idle),
-        wren: Some(esp_rom_opiflash_wren),
-        erase_area: Some(esp_rom_opiflash_erase_area),
-    };
-
-    unsafe {
-        let funcs_iram = &raw const FUNCS;
-        rom_spiflash_legacy_funcs =
-            ((funcs_iram as usize) - 0x4038_0400 + 0x3FC9_0400) as *const spiflash_legacy_funcs_t;
-    }
-}
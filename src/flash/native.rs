@@ -0,0 +1,278 @@
+//! Direct SPI1 register access for SPI flash, using the controller's built-in flash commands.
+//!
+//! Unlike the ROM functions, operations return as soon as the flash has accepted them. The next
+//! operation waits for the flash to become idle first, so the caller can go on decompressing
+//! while the last page is being programmed or a block is being erased.
+//!
+//! The ROM functions stay in use unless the host asks for this driver with `UseNativeFlash`.
+
+use super::{read_spi_reg, rom::RomFlash, spi_send_command, write_spi_reg, FlashBackend};
+
+// cmd register
+const FLASH_WREN: u32 = 1 << 30;
+const FLASH_PP: u32 = 1 << 25;
+const FLASH_SE: u32 = 1 << 24;
+const FLASH_BE: u32 = 1 << 23;
+const USR: u32 = 1 << 18;
+
+// ctrl register
+const FREAD_MODE_MASK: u32 = (1 << 24) | (1 << 23) | (1 << 20) | (1 << 14);
+
+// user register
+const USR_COMMAND: u32 = 1 << 31;
+const USR_ADDR: u32 = 1 << 30;
+const USR_DUMMY: u32 = 1 << 29;
+const USR_MISO: u32 = 1 << 28;
+const USR_MOSI: u32 = 1 << 27;
+const FWRITE_MODE_MASK: u32 = 0xF << 12;
+
+// user1 register
+const USR_ADDR_BITLEN: u32 = 26;
+
+// user2 register
+const USR_COMMAND_BITLEN: u32 = 28;
+
+const CMD_RDSR: u32 = 0x05;
+const CMD_FAST_READ: u32 = 0x0B;
+const SR_WIP: u32 = 1 << 0;
+const SR_WEL: u32 = 1 << 1;
+
+/// What the ROM functions return when the flash doesn't take a command.
+const RESULT_ERR: i32 = 1;
+
+/// The built-in commands only take 24-bit addresses.
+const ADDRESS_LIMIT: u32 = 1 << 24;
+
+const FLASH_PAGE_SIZE: u32 = 256;
+/// Size of the controller's data buffer.
+const BUFFER_WORDS: usize = 16;
+
+/// Returns this driver if it can be used instead of the ROM functions. The ESP32's SPI controller
+/// is left to the ROM.
+pub fn attach() -> Option<&'static dyn FlashBackend> {
+    if cfg!(feature = "esp32") || !probe() {
+        return None;
+    }
+
+    Some(&NativeFlash)
+}
+
+/// Checks that reading the start of the bootloader returns the same bytes as the ROM does. Blank or
+/// zeroed flash would read the same even if the read command didn't work, so it doesn't pass.
+fn probe() -> bool {
+    const PROBE_LEN: usize = 16;
+
+    let mut rom = core::mem::MaybeUninit::<[u8; PROBE_LEN]>::uninit();
+    let mut native = core::mem::MaybeUninit::<[u8; PROBE_LEN]>::uninit();
+    let (rom, native) = unsafe {
+        (
+            core::slice::from_raw_parts_mut(rom.as_mut_ptr().cast::<u8>(), PROBE_LEN),
+            core::slice::from_raw_parts_mut(native.as_mut_ptr().cast::<u8>(), PROBE_LEN),
+        )
+    };
+
+    let address = crate::properties::BOOTLOADER_OFFSET;
+    if RomFlash.read(address, rom) != 0 || read(address, native) != 0 {
+        return false;
+    }
+
+    let uniform = rom.iter().all(|byte| Some(byte) == rom.first());
+
+    !uniform && rom.iter().eq(native.iter())
+}
+
+pub struct NativeFlash;
+
+impl NativeFlash {
+    /// Whether `address..address + len` can be reached with the built-in commands. If not, waits
+    /// for the flash to finish what this driver started so the ROM can take over.
+    fn reachable(&self, address: u32, len: u32) -> bool {
+        if address.saturating_add(len) <= ADDRESS_LIMIT {
+            return true;
+        }
+
+        wait_idle();
+        false
+    }
+}
+
+impl FlashBackend for NativeFlash {
+    fn erase_sector(&self, address: u32) -> i32 {
+        if !self.reachable(address, crate::properties::FLASH_SECTOR_SIZE) {
+            return RomFlash.erase_sector(address);
+        }
+
+        erase(address, FLASH_SE)
+    }
+
+    fn erase_block(&self, address: u32) -> i32 {
+        if !self.reachable(address, crate::properties::FLASH_BLOCK_SIZE) {
+            return RomFlash.erase_block(address);
+        }
+
+        erase(address, FLASH_BE)
+    }
+
+    fn erase_chip(&self) -> i32 {
+        wait_idle();
+        RomFlash.erase_chip()
+    }
+
+    fn write(&self, address: u32, data: &[u8]) -> i32 {
+        if !self.reachable(address, data.len() as u32) {
+            return RomFlash.write(address, data);
+        }
+
+        write(address, data)
+    }
+
+    fn read(&self, address: u32, data: &mut [u8]) -> i32 {
+        if !self.reachable(address, data.len() as u32) {
+            return RomFlash.read(address, data);
+        }
+
+        read(address, data)
+    }
+
+    fn read_id(&self) -> u32 {
+        const RDID: u32 = 0x9F;
+
+        wait_idle();
+        spi_send_command(RDID, 24)
+    }
+
+    fn read_status(&self) -> Result<u32, i32> {
+        Ok(spi_send_command(CMD_RDSR, 8))
+    }
+
+    fn wait_idle(&self) -> i32 {
+        wait_idle()
+    }
+}
+
+/// Waits until the flash has finished the last program or erase operation.
+fn wait_idle() -> i32 {
+    while spi_send_command(CMD_RDSR, 8) & SR_WIP != 0 {}
+
+    0
+}
+
+fn execute(command: u32) {
+    let regs = crate::chip::MEM_SPI;
+
+    write_spi_reg(regs.cmd(), command);
+    while read_spi_reg(regs.cmd()) & command != 0 {}
+}
+
+/// Sets the write enable latch, and checks that the flash did.
+fn write_enable() -> bool {
+    wait_idle();
+    execute(FLASH_WREN);
+
+    spi_send_command(CMD_RDSR, 8) & SR_WEL != 0
+}
+
+fn erase(address: u32, command: u32) -> i32 {
+    let regs = crate::chip::MEM_SPI;
+
+    if !write_enable() {
+        return RESULT_ERR;
+    }
+    write_spi_reg(regs.addr(), address);
+    execute(command);
+
+    0
+}
+
+/// Programs `data` at `address`. The length doesn't need to be a multiple of 4.
+fn write(mut address: u32, mut data: &[u8]) -> i32 {
+    let regs = crate::chip::MEM_SPI;
+
+    // The built-in page program would also use the dummy cycles and the data lines the ROM set up
+    // for reading.
+    let old_user = read_spi_reg(regs.user());
+    write_spi_reg(regs.user(), old_user & !(USR_DUMMY | FWRITE_MODE_MASK));
+
+    let mut status = 0;
+    while !data.is_empty() {
+        // A program operation can't cross a flash page.
+        let page_left = FLASH_PAGE_SIZE - address % FLASH_PAGE_SIZE;
+        let chunk_len = data.len().min(BUFFER_WORDS * 4).min(page_left as usize);
+        let (chunk, rest) = data.split_at(chunk_len);
+
+        if !write_enable() {
+            status = RESULT_ERR;
+            break;
+        }
+
+        let mut buf_reg = regs.data_buf_0();
+        for word in chunk.chunks(4) {
+            // Programming 0xFF leaves the bytes past the end as they are.
+            let mut bytes = [0xFF; 4];
+            for (dst, src) in bytes.iter_mut().zip(word.iter()) {
+                *dst = *src;
+            }
+            write_spi_reg(buf_reg, u32::from_le_bytes(bytes));
+            buf_reg += 4;
+        }
+
+        write_spi_reg(regs.addr(), address | ((chunk_len as u32) << 24));
+        execute(FLASH_PP);
+
+        address += chunk_len as u32;
+        data = rest;
+    }
+
+    write_spi_reg(regs.user(), old_user);
+
+    status
+}
+
+/// Reads with the single line fast read command, regardless of the mode the ROM set up.
+fn read(mut address: u32, mut data: &mut [u8]) -> i32 {
+    let regs = crate::chip::MEM_SPI;
+
+    wait_idle();
+
+    let old_ctrl = read_spi_reg(regs.ctrl());
+    let old_user = read_spi_reg(regs.user());
+    let old_user1 = read_spi_reg(regs.user1());
+    let old_user2 = read_spi_reg(regs.user2());
+
+    write_spi_reg(regs.ctrl(), old_ctrl & !FREAD_MODE_MASK);
+    write_spi_reg(
+        regs.user(),
+        (old_user & !(FWRITE_MODE_MASK | USR_MOSI)) | USR_COMMAND | USR_ADDR | USR_DUMMY | USR_MISO,
+    );
+    // 24 address bits and 8 dummy cycles.
+    write_spi_reg(regs.user1(), (23 << USR_ADDR_BITLEN) | 7);
+    write_spi_reg(regs.user2(), (7 << USR_COMMAND_BITLEN) | CMD_FAST_READ);
+
+    while !data.is_empty() {
+        let chunk_len = data.len().min(BUFFER_WORDS * 4);
+        let (chunk, rest) = core::mem::take(&mut data).split_at_mut(chunk_len);
+
+        write_spi_reg(regs.addr(), address);
+        write_spi_reg(regs.miso_dlen(), chunk_len as u32 * 8 - 1);
+        execute(USR);
+
+        let mut buf_reg = regs.data_buf_0();
+        for word in chunk.chunks_mut(4) {
+            let bytes = read_spi_reg(buf_reg).to_le_bytes();
+            for (dst, src) in word.iter_mut().zip(bytes.iter()) {
+                *dst = *src;
+            }
+            buf_reg += 4;
+        }
+
+        address += chunk_len as u32;
+        data = rest;
+    }
+
+    write_spi_reg(regs.ctrl(), old_ctrl);
+    write_spi_reg(regs.user(), old_user);
+    write_spi_reg(regs.user1(), old_user1);
+    write_spi_reg(regs.user2(), old_user2);
+
+    0
+}
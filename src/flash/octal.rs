@@ -0,0 +1,123 @@
+//! Octal flash on the ESP32-S3, through the ROM's OPI functions.
+
+use super::{rom::RomFlash, FlashBackend};
+
+#[allow(non_camel_case_types)]
+mod s3 {
+    type spi_flash_func_t = unsafe extern "C" fn();
+    type spi_flash_op_t = unsafe extern "C" fn() -> i32;
+    type spi_flash_erase_t = unsafe extern "C" fn(u32) -> i32;
+    type spi_flash_rd_t = unsafe extern "C" fn(u32, *mut (), i32) -> i32;
+    type spi_flash_wr_t = unsafe extern "C" fn(u32, *const u32, i32) -> i32;
+    type spi_flash_ewr_t = unsafe extern "C" fn(u32, *const (), u32) -> i32;
+    type spi_flash_wren_t = unsafe extern "C" fn(*mut ()) -> i32;
+    type spi_flash_erase_area_t = unsafe extern "C" fn(u32, u32) -> i32;
+
+    #[repr(C)]
+    pub struct spiflash_legacy_funcs_t {
+        pub pp_addr_bit_len: u8,
+        pub se_addr_bit_len: u8,
+        pub be_addr_bit_len: u8,
+        pub rd_addr_bit_len: u8,
+        pub read_sub_len: u32,
+        pub write_sub_len: u32,
+        pub unlock: Option<spi_flash_op_t>,
+        pub erase_sector: Option<spi_flash_erase_t>,
+        pub erase_block: Option<spi_flash_erase_t>,
+        pub read: Option<spi_flash_rd_t>,
+        pub write: Option<spi_flash_wr_t>,
+        pub encrypt_write: Option<spi_flash_ewr_t>,
+        pub check_sus: Option<spi_flash_func_t>,
+        pub wren: Option<spi_flash_wren_t>,
+        pub wait_idle: Option<spi_flash_op_t>,
+        pub erase_area: Option<spi_flash_erase_area_t>,
+    }
+}
+
+use s3::*;
+
+extern "C" {
+    static mut rom_spiflash_legacy_funcs: *const spiflash_legacy_funcs_t;
+
+    fn ets_efuse_flash_octal_mode() -> bool;
+
+    fn esp_rom_opiflash_wait_idle() -> i32;
+    fn esp_rom_opiflash_erase_block_64k(addr: u32) -> i32;
+    fn esp_rom_opiflash_erase_sector(addr: u32) -> i32;
+    fn esp_rom_opiflash_read(addr: u32, buf: *mut (), len: i32) -> i32;
+    fn esp_rom_opiflash_write(addr: u32, data: *const u32, len: i32) -> i32;
+    fn esp_rom_opiflash_wren(p: *mut ()) -> i32;
+    fn esp_rom_opiflash_erase_area(start_addr: u32, end_addr: u32) -> i32;
+}
+
+/// Whether the eFuses say the chip has octal flash.
+pub fn enabled() -> bool {
+    unsafe { ets_efuse_flash_octal_mode() }
+}
+
+/// Sets up the ROM for octal flash, if the chip has it.
+pub fn attach() -> Option<&'static dyn FlashBackend> {
+    if !enabled() {
+        return None;
+    }
+
+    init_ospi_funcs();
+
+    Some(&OctalFlash)
+}
+
+/// The generic ROM functions go through the function table `init_ospi_funcs` patches.
+pub struct OctalFlash;
+
+impl FlashBackend for OctalFlash {
+    fn erase_sector(&self, address: u32) -> i32 {
+        RomFlash.erase_sector(address)
+    }
+
+    fn erase_block(&self, address: u32) -> i32 {
+        RomFlash.erase_block(address)
+    }
+
+    fn erase_chip(&self) -> i32 {
+        RomFlash.erase_chip()
+    }
+
+    fn write(&self, address: u32, data: &[u8]) -> i32 {
+        RomFlash.write(address, data)
+    }
+
+    fn read(&self, address: u32, data: &mut [u8]) -> i32 {
+        RomFlash.read(address, data)
+    }
+
+    fn wait_idle(&self) -> i32 {
+        unsafe { esp_rom_opiflash_wait_idle() }
+    }
+}
+
+fn init_ospi_funcs() {
+    static FUNCS: spiflash_legacy_funcs_t = spiflash_legacy_funcs_t {
+        pp_addr_bit_len: 24,
+        se_addr_bit_len: 24,
+        be_addr_bit_len: 24,
+        rd_addr_bit_len: 24,
+        read_sub_len: 16,
+        write_sub_len: 32,
+        unlock: Some(esp_rom_opiflash_wait_idle),
+        erase_block: Some(esp_rom_opiflash_erase_block_64k),
+        erase_sector: Some(esp_rom_opiflash_erase_sector),
+        read: Some(esp_rom_opiflash_read),
+        write: Some(esp_rom_opiflash_write),
+        encrypt_write: None,
+        check_sus: None,
+        wait_idle: Some(esp_rom_opiflash_wait_idle),
+        wren: Some(esp_rom_opiflash_wren),
+        erase_area: Some(esp_rom_opiflash_erase_area),
+    };
+
+    unsafe {
+        let funcs_iram = &raw const FUNCS;
+        rom_spiflash_legacy_funcs =
+            ((funcs_iram as usize) - 0x4038_0400 + 0x3FC9_0400) as *const spiflash_legacy_funcs_t;
+    }
+}
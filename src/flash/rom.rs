@@ -0,0 +1,67 @@
+//! Quad (and slower) SPI flash through the ROM functions.
+
+use super::FlashBackend;
+use crate::properties::{FLASH_BLOCK_SIZE, FLASH_SECTOR_SIZE};
+
+extern "C" {
+    // fn esp_rom_spiflash_write_encrypted_enable();
+    // fn esp_rom_spiflash_write_encrypted_disable();
+    // fn esp_rom_spiflash_write_encrypted(addr: u32, data: *const u8, len: u32);
+    // fn esp_rom_spiflash_select_qio_pins();
+    // fn esp_rom_spi_flash_auto_sus_res();
+    // fn esp_rom_spi_flash_send_resume();
+    // fn esp_rom_spi_flash_update_id();
+    // fn esp_rom_spiflash_config_clk();
+    // fn esp_rom_spiflash_config_readmode();
+    // fn esp_rom_spiflash_read_status(/* esp_rom_spiflash_chip_t *spi ,*/ status: *mut u32);
+    // fn esp_rom_spiflash_read_statushigh(/* esp_rom_spiflash_chip_t *spi ,*/ status: *mut u32);
+    // fn esp_rom_spiflash_write_status(/* esp_rom_spiflash_chip_t *spi ,*/ status: *mut u32);
+
+    fn esp_rom_spiflash_erase_chip() -> i32;
+    fn esp_rom_spiflash_erase_block(block_number: u32) -> i32;
+    fn esp_rom_spiflash_erase_sector(sector_number: u32) -> i32;
+    /// address (4 byte alignment), data, length
+    fn esp_rom_spiflash_write(dest_addr: u32, data: *const u8, len: u32) -> i32;
+    /// address (4 byte alignment), data, length
+    fn esp_rom_spiflash_read(src_addr: u32, data: *mut u8, len: u32) -> i32;
+    fn esp_rom_spiflash_read_user_cmd(status: *mut u32, cmd: u8) -> i32;
+    // fn esp_rom_spiflash_unlock() -> i32;
+    // fn esp_rom_spiflash_lock(); // can't find in idf defs?
+}
+
+pub struct RomFlash;
+
+impl FlashBackend for RomFlash {
+    fn erase_sector(&self, address: u32) -> i32 {
+        unsafe { esp_rom_spiflash_erase_sector(address / FLASH_SECTOR_SIZE) }
+    }
+
+    fn erase_block(&self, address: u32) -> i32 {
+        unsafe { esp_rom_spiflash_erase_block(address / FLASH_BLOCK_SIZE) }
+    }
+
+    fn erase_chip(&self) -> i32 {
+        unsafe { esp_rom_spiflash_erase_chip() }
+    }
+
+    fn write(&self, address: u32, data: &[u8]) -> i32 {
+        let len = data.len() as u32;
+        unsafe { esp_rom_spiflash_write(address, data.as_ptr(), len) }
+    }
+
+    fn read(&self, address: u32, data: &mut [u8]) -> i32 {
+        let len = data.len() as u32;
+        unsafe { esp_rom_spiflash_read(address, data.as_mut_ptr(), len) }
+    }
+}
+
+/// Reads the status register with the ROM's generic command function.
+pub fn read_status() -> Result<u32, i32> {
+    const RDSR: u8 = 0x05;
+
+    let mut status = 0;
+    match unsafe { esp_rom_spiflash_read_user_cmd(&mut status, RDSR) } {
+        0 => Ok(status),
+        res => Err(res),
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    properties::{BOOTLOADER_REGION_END, FLASH_SECTOR_SIZE},
+    ERROR_BASE_INTERNAL,
+};
+
+pub const MAX_PROTECTED_RANGES: usize = 8;
+
+#[derive(Clone, Copy)]
+struct Range {
+    start: u32,
+    end: u32,
+}
+
+impl Range {
+    const fn empty() -> Self {
+        Self { start: 0, end: 0 }
+    }
+
+    fn overlaps(&self, address: u32, len: u32) -> bool {
+        len > 0 && address < self.end && self.start < address.saturating_add(len)
+    }
+}
+
+/// Decides which parts of the flash may be erased or programmed.
+pub struct FlashGuard {
+    secure_boot: bool,
+    bootloader_unlocked: bool,
+    ranges: [Range; MAX_PROTECTED_RANGES],
+    range_count: usize,
+}
+
+impl FlashGuard {
+    pub const fn new() -> Self {
+        Self {
+            secure_boot: false,
+            bootloader_unlocked: false,
+            ranges: [Range::empty(); MAX_PROTECTED_RANGES],
+            range_count: 0,
+        }
+    }
+
+    pub fn init(&mut self) {
+        self.secure_boot = crate::efuse::secure_boot_enabled();
+        self.bootloader_unlocked = false;
+        self.range_count = 0;
+
+        if self.secure_boot {
+            dprintln!("Secure boot enabled, bootloader region locked");
+        }
+    }
+
+    pub fn unlock_bootloader(&mut self) {
+        self.bootloader_unlocked = true;
+    }
+
+    fn bootloader_locked(&self) -> bool {
+        self.secure_boot && !self.bootloader_unlocked
+    }
+
+    /// Replaces the read-only ranges. `ranges` holds (start, size) pairs, both of which must be
+    /// sector aligned. Leaves the old ranges in place if any of the new ones is invalid.
+    pub fn set_protected_ranges(&mut self, ranges: &[u32]) -> i32 {
+        if !ranges.len().is_multiple_of(2) || ranges.len() / 2 > MAX_PROTECTED_RANGES {
+            return ERROR_BASE_INTERNAL - 17;
+        }
+
+        let mut new_ranges = [Range::empty(); MAX_PROTECTED_RANGES];
+        for (pair, range) in ranges.chunks_exact(2).zip(new_ranges.iter_mut()) {
+            let (start, size) = (pair[0], pair[1]);
+            if !start.is_multiple_of(FLASH_SECTOR_SIZE) || !size.is_multiple_of(FLASH_SECTOR_SIZE) {
+                return ERROR_BASE_INTERNAL - 17;
+            }
+            let Some(end) = start.checked_add(size) else {
+                return ERROR_BASE_INTERNAL - 17;
+            };
+
+            *range = Range { start, end };
+        }
+
+        self.ranges = new_ranges;
+        self.range_count = ranges.len() / 2;
+        for range in self.ranges.iter().take(self.range_count) {
+            dprintln!(
+                "PROTECT {} bytes @ {}",
+                range.end - range.start,
+                range.start
+            );
+        }
+
+        0
+    }
+
+    pub fn has_protected_ranges(&self) -> bool {
+        self.range_count > 0
+    }
+
+    /// Returns whether any of the `len` bytes starting at `address` is in a protected range.
+    pub fn is_protected(&self, address: u32, len: u32) -> bool {
+        self.ranges
+            .iter()
+            .take(self.range_count)
+            .any(|range| range.overlaps(address, len))
+    }
+
+    /// Returns 0 if `len` bytes starting at `address` may be modified.
+    pub fn check(&self, address: u32, len: u32) -> i32 {
+        if self.bootloader_locked() && address < BOOTLOADER_REGION_END && len > 0 {
+            dprintln!("ERROR {} is in the locked bootloader region", address);
+            return ERROR_BASE_INTERNAL - 7;
+        }
+
+        if self.is_protected(address, len) {
+            dprintln!("ERROR {} is in a protected range", address);
+            return ERROR_BASE_INTERNAL - 8;
+        }
+
+        0
+    }
+}
@@ -0,0 +1,337 @@
+//! Rewrites the SPI flash settings in the bootloader image header while it is being written, like
+//! esptool's `--flash_mode`, `--flash_size` and `--flash_freq`.
+//!
+//! Changing the header invalidates a Secure Boot signature. The header word and the appended digest
+//! are therefore written last, once we know whether a signature block follows the image, and a
+//! signed image keeps its own.
+
+use core::mem::MaybeUninit;
+
+use crate::{
+    image::ImageHeader,
+    properties::{BOOTLOADER_OFFSET, FLASH_SECTOR_SIZE},
+    sha::Sha256,
+    ERROR_BASE_INTERNAL,
+};
+
+const HEADER_WORD_LEN: u32 = 4;
+const DIGEST_LEN: u32 = 32;
+
+/// The first byte of a Secure Boot V2 signature block.
+const SIGNATURE_BLOCK_MAGIC: u8 = 0xE7;
+
+pub struct HeaderPatch {
+    mode: Option<u8>,
+    size: Option<u8>,
+    freq: Option<u8>,
+
+    /// Offset in the bootloader image of the next byte we expect, while one is being written.
+    position: Option<u32>,
+    /// The first word of the image, with the new settings and as it came.
+    header_word: u32,
+    original_header_word: u32,
+    /// Offset of the next segment header, and after the last segment, of the checksum padding.
+    cursor: u32,
+    segments_left: u8,
+    segment_header: [u8; 8],
+    segment_header_len: usize,
+    /// The appended SHA-256 covers the header, so it has to be recomputed. The XOR checksum only
+    /// covers the segment data.
+    hash_appended: bool,
+    sha: Option<Sha256>,
+    digest_start: Option<u32>,
+    digest: MaybeUninit<[u32; 8]>,
+    original_digest: [u8; DIGEST_LEN as usize],
+    /// Where a signature block would start, once the end of the image is known.
+    signature_start: Option<u32>,
+}
+
+impl HeaderPatch {
+    pub const fn new() -> Self {
+        Self {
+            mode: None,
+            size: None,
+            freq: None,
+            position: None,
+            header_word: 0,
+            original_header_word: 0,
+            cursor: 0,
+            segments_left: 0,
+            segment_header: [0; 8],
+            segment_header_len: 0,
+            hash_appended: false,
+            sha: None,
+            digest_start: None,
+            digest: MaybeUninit::uninit(),
+            original_digest: [0; DIGEST_LEN as usize],
+            signature_start: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.mode = None;
+        self.size = None;
+        self.freq = None;
+        self.position = None;
+    }
+
+    /// Sets the values to write into the header. `u32::MAX` keeps what the image has. `mode` and
+    /// `freq` are the header's codes, `size` is in bytes, 0 meaning the detected flash size.
+    pub fn configure(&mut self, mode: u32, size: u32, freq: u32) -> i32 {
+        const KEEP: u32 = u32::MAX;
+
+        // The ROM would refuse a bootloader whose header no longer matches its signature.
+        if (mode, size, freq) != (KEEP, KEEP, KEEP) && crate::efuse::secure_boot_enabled() {
+            dprintln!("ERROR secure boot is enabled, flash settings can't be changed");
+            return ERROR_BASE_INTERNAL - 14;
+        }
+
+        let size = match size {
+            // -1 would turn into `KEEP`.
+            0 => match crate::flash::get_flash_size() {
+                detected if detected > 0 => detected as u32,
+                _ => {
+                    dprintln!("ERROR flash size unknown");
+                    return ERROR_BASE_INTERNAL - 22;
+                }
+            },
+            size => size,
+        };
+
+        // 1 MB is 0, each following code doubles the size
+        let size_code = size.trailing_zeros().wrapping_sub(20);
+        if (mode != KEEP && mode > 3)
+            || (freq != KEEP && freq > 0xF)
+            || (size != KEEP && (!size.is_power_of_two() || size_code > 7))
+        {
+            return ERROR_BASE_INTERNAL - 21;
+        }
+
+        self.mode = (mode != KEEP).then_some(mode as u8);
+        self.size = (size != KEEP).then_some(size_code as u8);
+        self.freq = (freq != KEEP).then_some(freq as u8);
+        self.position = None;
+
+        0
+    }
+
+    fn enabled(&self) -> bool {
+        self.mode.is_some() || self.size.is_some() || self.freq.is_some()
+    }
+
+    /// Passes `data` on to `sink`, patching it if it is part of the bootloader image.
+    pub fn apply(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        sink: &mut dyn FnMut(u32, &[u8]) -> i32,
+    ) -> i32 {
+        if address == BOOTLOADER_OFFSET && self.enabled() {
+            self.start(data);
+        }
+
+        let Some(offset) = self.position.filter(|p| BOOTLOADER_OFFSET + p == address) else {
+            self.position = None;
+            return sink(address, data);
+        };
+
+        let end = offset + data.len() as u32;
+
+        self.parse_segments(offset, data);
+        if self.segments_left == 0 && self.signature_start.is_none() {
+            let checksum_end = (self.cursor | 15) + 1;
+            let image_end = if self.hash_appended {
+                self.digest_start = Some(checksum_end);
+                checksum_end + DIGEST_LEN
+            } else {
+                checksum_end
+            };
+            self.signature_start = Some(image_end.next_multiple_of(FLASH_SECTOR_SIZE));
+        }
+
+        self.hash(offset, data);
+        self.keep_original_digest(offset, data);
+
+        let status = self.emit(offset, data, sink);
+        if status != 0 {
+            return status;
+        }
+        self.position = Some(end);
+
+        match self.signature_start.filter(|start| end > *start) {
+            Some(start) => {
+                let first = start.checked_sub(offset).and_then(|i| data.get(i as usize));
+                self.finish_header(first == Some(&SIGNATURE_BLOCK_MAGIC), sink)
+            }
+            None => 0,
+        }
+    }
+
+    /// Writes the header of a bootloader image whose stream ended before we could see whether a
+    /// signature block follows it.
+    pub fn finish(&mut self, sink: &mut dyn FnMut(u32, &[u8]) -> i32) -> i32 {
+        if self.position.is_none() {
+            return 0;
+        }
+
+        self.finish_header(false, sink)
+    }
+
+    fn start(&mut self, data: &[u8]) {
+        self.position = None;
+
+        let Ok(header) = ImageHeader::parse(data) else {
+            return;
+        };
+        let Some(&[magic, segments, mode, size_freq]) = data.first_chunk::<4>() else {
+            return;
+        };
+
+        let mode = self.mode.unwrap_or(mode);
+        let size = self.size.unwrap_or(size_freq >> 4);
+        let freq = self.freq.unwrap_or(size_freq & 0xF);
+        self.header_word = u32::from_le_bytes([magic, segments, mode, (size << 4) | freq]);
+        self.original_header_word = u32::from_le_bytes([magic, segments, mode, size_freq]);
+
+        self.position = Some(0);
+        self.cursor = crate::image::HEADER_LEN as u32;
+        self.segments_left = header.segment_count;
+        self.segment_header_len = 0;
+        self.hash_appended = header.hash_appended;
+        self.sha = header.hash_appended.then(Sha256::new);
+        self.digest_start = None;
+        self.signature_start = None;
+
+        dprintln!("PATCH bootloader header {}", self.header_word);
+    }
+
+    /// Follows the segment headers to find where the image ends.
+    fn parse_segments(&mut self, offset: u32, data: &[u8]) {
+        while self.segments_left > 0 {
+            let at = self.cursor + self.segment_header_len as u32;
+            let Some(byte) = at.checked_sub(offset).and_then(|i| data.get(i as usize)) else {
+                break;
+            };
+            let Some(slot) = self.segment_header.get_mut(self.segment_header_len) else {
+                break;
+            };
+            *slot = *byte;
+            self.segment_header_len += 1;
+
+            if self.segment_header_len == self.segment_header.len() {
+                let [_, _, _, _, l0, l1, l2, l3] = self.segment_header;
+                let len = u32::from_le_bytes([l0, l1, l2, l3]);
+
+                self.cursor += self.segment_header.len() as u32 + len;
+                self.segment_header_len = 0;
+                self.segments_left -= 1;
+            }
+        }
+    }
+
+    fn hash(&mut self, offset: u32, data: &[u8]) {
+        let Some(sha) = self.sha.as_mut() else {
+            return;
+        };
+
+        let end = offset + data.len() as u32;
+        let hash_end = self
+            .digest_start
+            .map_or(end, |digest_start| digest_start.min(end));
+
+        let mut from = offset;
+        if from < HEADER_WORD_LEN {
+            let header = word_bytes(&self.header_word);
+            sha.update(header.get(from as usize..).unwrap_or(&[]));
+            from = HEADER_WORD_LEN;
+        }
+        if from < hash_end {
+            let range = (from - offset) as usize..(hash_end - offset) as usize;
+            sha.update(data.get(range).unwrap_or(&[]));
+        }
+
+        if let Some(digest_start) = self.digest_start {
+            if end >= digest_start {
+                if let Some(sha) = self.sha.take() {
+                    let digest = unsafe { &mut *self.digest.as_mut_ptr().cast() };
+                    sha.finish(digest);
+                }
+            }
+        }
+    }
+
+    /// Remembers the digest the image came with, in case it turns out to be signed.
+    fn keep_original_digest(&mut self, offset: u32, data: &[u8]) {
+        let Some(digest_start) = self.digest_start else {
+            return;
+        };
+
+        for (at, byte) in (digest_start..).zip(self.original_digest.iter_mut()) {
+            if let Some(data_byte) = at.checked_sub(offset).and_then(|i| data.get(i as usize)) {
+                *byte = *data_byte;
+            }
+        }
+    }
+
+    /// Sends `data` to `sink`, leaving out the header word and the digest.
+    fn emit(&self, offset: u32, data: &[u8], sink: &mut dyn FnMut(u32, &[u8]) -> i32) -> i32 {
+        let end = offset + data.len() as u32;
+        let digest_start = self.digest_start.unwrap_or(u32::MAX);
+        let digest_end = digest_start.saturating_add(DIGEST_LEN);
+
+        for (from, to) in [(HEADER_WORD_LEN, digest_start), (digest_end, u32::MAX)] {
+            let from = from.max(offset);
+            let to = to.min(end);
+            if from >= to {
+                continue;
+            }
+
+            let piece = data.get((from - offset) as usize..(to - offset) as usize);
+            let status = sink(BOOTLOADER_OFFSET + from, piece.unwrap_or(&[]));
+            if status != 0 {
+                return status;
+            }
+        }
+
+        0
+    }
+
+    /// Writes the header word and the digest, the patched ones unless the image is signed.
+    fn finish_header(&mut self, signed: bool, sink: &mut dyn FnMut(u32, &[u8]) -> i32) -> i32 {
+        self.position = None;
+
+        let computed_digest = unsafe {
+            core::slice::from_raw_parts(self.digest.as_ptr().cast::<u8>(), DIGEST_LEN as usize)
+        };
+        let (header_word, digest) = if signed {
+            (&self.original_header_word, &self.original_digest[..])
+        } else {
+            (&self.header_word, computed_digest)
+        };
+
+        let status = sink(BOOTLOADER_OFFSET, word_bytes(header_word));
+        if status != 0 {
+            return status;
+        }
+
+        // Only once all of the image before it went through the hash.
+        let digest_ready = self.hash_appended && self.sha.is_none();
+        if let Some(digest_start) = self.digest_start.filter(|_| digest_ready) {
+            let status = sink(BOOTLOADER_OFFSET + digest_start, digest);
+            if status != 0 {
+                return status;
+            }
+        }
+
+        if signed {
+            dprintln!("ERROR bootloader is signed, flash settings can't be changed");
+            return ERROR_BASE_INTERNAL - 14;
+        }
+
+        0
+    }
+}
+
+fn word_bytes(word: &u32) -> &[u8] {
+    unsafe { core::slice::from_raw_parts((word as *const u32).cast::<u8>(), 4) }
+}
@@ -0,0 +1,271 @@
+//! ESP app and bootloader image checks.
+
+use core::mem::MaybeUninit;
+
+use crate::{
+    flash, partition::PartitionTable, properties::BOOTLOADER_OFFSET, sha::Sha256, ERROR_BASE_IMAGE,
+};
+
+const IMAGE_MAGIC: u8 = 0xE9;
+const MAX_SEGMENTS: u8 = 16;
+const CHECKSUM_SEED: u8 = 0xEF;
+
+pub const HEADER_LEN: usize = 24;
+const SEGMENT_HEADER_LEN: usize = 8;
+const DIGEST_LEN: usize = 32;
+
+const PARTITION_TYPE_APP: u8 = 0x00;
+
+/// `esp_app_desc_t` follows the header of the first segment.
+const APP_DESC_OFFSET: usize = HEADER_LEN + SEGMENT_HEADER_LEN;
+const APP_DESC_MAGIC: u32 = 0xABCD5432;
+
+/// How much of the start of an image the checks before writing it look at.
+pub const HEAD_LEN: usize = APP_DESC_OFFSET + 8;
+
+/// The fixed and extended header at the start of every image.
+pub struct ImageHeader {
+    pub segment_count: u8,
+    pub chip_id: u16,
+    /// Revisions are `major * 100 + minor`.
+    pub min_chip_rev_full: u16,
+    pub max_chip_rev_full: u16,
+    pub hash_appended: bool,
+}
+
+impl ImageHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, i32> {
+        let Some(header) = bytes.first_chunk::<HEADER_LEN>() else {
+            return Err(ERROR_BASE_IMAGE - 1);
+        };
+        let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+
+        let segment_count = header[1];
+        if header[0] != IMAGE_MAGIC || segment_count > MAX_SEGMENTS {
+            return Err(ERROR_BASE_IMAGE - 1);
+        }
+
+        Ok(Self {
+            segment_count,
+            chip_id: u16_at(12),
+            min_chip_rev_full: u16_at(15),
+            max_chip_rev_full: u16_at(17),
+            hash_appended: header[23] == 1,
+        })
+    }
+
+    /// Refuses images built for another chip or for revisions outside of what this chip is.
+    pub fn check_target(&self) -> i32 {
+        if self.chip_id != crate::chip::IMAGE_CHIP_ID {
+            dprintln!(
+                "ERROR image is for chip id {}, not {}",
+                self.chip_id,
+                crate::chip::IMAGE_CHIP_ID
+            );
+            return ERROR_BASE_IMAGE - 6;
+        }
+
+        let revision = crate::efuse::read_chip_revision();
+        let max_set = self.max_chip_rev_full != 0 && self.max_chip_rev_full != 0xFFFF;
+        if revision < self.min_chip_rev_full as u32
+            || (max_set && revision > self.max_chip_rev_full as u32)
+        {
+            dprintln!(
+                "ERROR image needs revision {} to {}, chip is {}",
+                self.min_chip_rev_full,
+                self.max_chip_rev_full,
+                revision
+            );
+            return ERROR_BASE_IMAGE - 7;
+        }
+
+        0
+    }
+}
+
+/// Refuses apps whose `secure_version` is below the anti-rollback eFuse counter, which the
+/// bootloader would not boot. `data` is the start of the image.
+pub fn check_secure_version(address: u32, data: &[u8]) -> i32 {
+    let min_version = crate::efuse::secure_version();
+    if address == BOOTLOADER_OFFSET || min_version == 0 {
+        return 0;
+    }
+
+    let Some(desc) = data
+        .get(APP_DESC_OFFSET..)
+        .and_then(|desc| desc.first_chunk::<8>())
+    else {
+        return ERROR_BASE_IMAGE - 1;
+    };
+    let magic = u32::from_le_bytes([desc[0], desc[1], desc[2], desc[3]]);
+    let secure_version = u32::from_le_bytes([desc[4], desc[5], desc[6], desc[7]]);
+
+    if magic != APP_DESC_MAGIC {
+        dprintln!("ERROR no app description, can't check secure version");
+        return ERROR_BASE_IMAGE - 1;
+    }
+    if secure_version < min_version {
+        dprintln!(
+            "ERROR app secure version {} is below the eFuse minimum {}",
+            secure_version,
+            min_version
+        );
+        return ERROR_BASE_IMAGE - 9;
+    }
+
+    0
+}
+
+/// Refuses an image about to be written at `address` if it's for another chip or revision, or
+/// below the anti-rollback counter. `data` is the start of the image, `HEAD_LEN` bytes of it if
+/// it's that long. Anything not written where an image is expected passes.
+pub fn check_head(address: u32, data: &[u8]) -> i32 {
+    if !expected_at(address) {
+        return 0;
+    }
+
+    match ImageHeader::parse(data) {
+        Ok(header) => match header.check_target() {
+            0 => check_secure_version(address, data),
+            status => status,
+        },
+        Err(status) => status,
+    }
+}
+
+/// Whether an image is expected at `address`: the bootloader offset or the start of an app
+/// partition.
+pub fn expected_at(address: u32) -> bool {
+    if address == BOOTLOADER_OFFSET {
+        return true;
+    }
+
+    let Ok(table) = PartitionTable::read() else {
+        return false;
+    };
+
+    let mut buf = MaybeUninit::uninit();
+    (0..table.len()).any(|index| {
+        table
+            .get(index, &mut buf)
+            .is_ok_and(|p| p.ty == PARTITION_TYPE_APP && p.offset == address)
+    })
+}
+
+/// Checks an image that has just been written at `address`. `buf` is scratch space for reading
+/// the flash.
+pub fn check_written(address: u32, buf: &mut [u8]) -> i32 {
+    let image_len = match validate(address, buf) {
+        Ok(len) => len,
+        Err(status) => return status,
+    };
+
+    // The ROM checks the bootloader itself.
+    if address != BOOTLOADER_OFFSET && crate::efuse::secure_boot_enabled() {
+        return crate::secure_boot::verify_app(address, image_len, buf);
+    }
+
+    0
+}
+
+/// Walks the image written at `address`, checking its segments, checksum and, if the header says
+/// one is appended, its SHA-256 digest. Returns the length of the image.
+pub fn validate(address: u32, buf: &mut [u8]) -> Result<u32, i32> {
+    Validator::new(address, buf).run()
+}
+
+struct Validator<'a> {
+    position: u32,
+    buf: &'a mut [u8],
+    checksum: u8,
+    sha: Option<Sha256>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(address: u32, buf: &'a mut [u8]) -> Self {
+        Self {
+            position: address,
+            buf,
+            checksum: CHECKSUM_SEED,
+            sha: None,
+        }
+    }
+
+    fn run(mut self) -> Result<u32, i32> {
+        let start = self.position;
+
+        let header = ImageHeader::parse(self.read(HEADER_LEN)?)?;
+        if header.hash_appended {
+            self.sha = Some(Sha256::new());
+        }
+        self.hash(HEADER_LEN);
+
+        for _ in 0..header.segment_count {
+            let &[_, _, _, _, l0, l1, l2, l3] = self.read(SEGMENT_HEADER_LEN)? else {
+                return Err(ERROR_BASE_IMAGE - 2);
+            };
+            let len = u32::from_le_bytes([l0, l1, l2, l3]);
+            self.hash(SEGMENT_HEADER_LEN);
+
+            if len > crate::properties::MAX_FLASH_SIZE {
+                return Err(ERROR_BASE_IMAGE - 2);
+            }
+
+            let mut remaining = len as usize;
+            while remaining > 0 {
+                let chunk = remaining.min(self.buf.len());
+                let checksum = self.checksum;
+                let data = self.read(chunk)?;
+                self.checksum = data.iter().fold(checksum, |acc, b| acc ^ b);
+                self.hash(chunk);
+                remaining -= chunk;
+            }
+        }
+
+        // The checksum byte is placed so that the image ends on a 16-byte boundary.
+        let len = self.position - start;
+        let padding = (15 - len % 16) as usize;
+        let tail = self.read(padding + 1)?;
+        let stored_checksum = tail.last().copied();
+        self.hash(padding + 1);
+
+        if stored_checksum != Some(self.checksum) {
+            dprintln!("ERROR image checksum mismatch");
+            return Err(ERROR_BASE_IMAGE - 3);
+        }
+
+        if let Some(sha) = self.sha.take() {
+            let mut digest = MaybeUninit::uninit();
+            let digest = sha.finish(&mut digest);
+
+            let stored = self.read(DIGEST_LEN)?;
+            if !stored.iter().eq(digest.iter()) {
+                dprintln!("ERROR image digest mismatch");
+                return Err(ERROR_BASE_IMAGE - 4);
+            }
+        }
+
+        Ok(self.position - start)
+    }
+
+    /// Reads the next `len` bytes of the image into the scratch buffer.
+    fn read(&mut self, len: usize) -> Result<&[u8], i32> {
+        let Some(data) = self.buf.get_mut(..len) else {
+            return Err(ERROR_BASE_IMAGE - 2);
+        };
+
+        if flash::read_flash(self.position, data) != 0 {
+            return Err(ERROR_BASE_IMAGE - 5);
+        }
+        self.position += len as u32;
+
+        Ok(data)
+    }
+
+    /// Feeds the last `len` bytes read into the digest.
+    fn hash(&mut self, len: usize) {
+        if let (Some(sha), Some(data)) = (self.sha.as_mut(), self.buf.get(..len)) {
+            sha.update(data);
+        }
+    }
+}
@@ -28,8 +28,17 @@ macro_rules! dprintln {
 #[cfg_attr(feature = "esp32h2", path = "chip/esp32h2.rs")]
 #[cfg_attr(feature = "esp32p4", path = "chip/esp32p4.rs")]
 mod chip;
+mod chip_info;
+mod crc;
 mod efuse;
+mod guard;
+mod header_patch;
+mod image;
+mod ota;
+mod partition;
 mod rom;
+mod secure_boot;
+mod sha;
 
 // Define necessary functions for flash loader
 //
@@ -40,10 +49,14 @@ mod rom;
 use core::mem::MaybeUninit;
 
 use chip::CpuSaveState;
+use guard::FlashGuard;
+use header_patch::HeaderPatch;
 use panic_never as _;
+use partition::KeptPartitions;
 
 use crate::tinfl::{
-    OutBuffer, TinflDecompressor, TINFL_STATUS_DONE, TINFL_STATUS_NEEDS_MORE_INPUT,
+    OutBuffer, TinflDecompressor, TINFL_STATUS_ADLER32_MISMATCH, TINFL_STATUS_DONE,
+    TINFL_STATUS_NEEDS_MORE_INPUT,
 };
 
 #[cfg_attr(any(target_arch = "xtensa"), path = "api_xtensa.rs")]
@@ -58,8 +71,12 @@ compile_error!("specify the target with `--target`");
 
 const ERROR_BASE_INTERNAL: i32 = -1000;
 const ERROR_BASE_TINFL: i32 = -2000;
+const ERROR_BASE_IMAGE: i32 = -3000;
 const ERROR_BASE_FLASH: i32 = -4000;
 
+// The `fnc` argument of Init and UnInit.
+const FNC_VERIFY: u32 = 3;
+
 // Reserve memory for the data buffer so that we can use `nm` to
 // get its location and we ensure nothing gets placed on top of it.
 #[unsafe(no_mangle)]
@@ -69,14 +86,26 @@ static mut PAGE_BUFFER: [MaybeUninit<u8>; 32 * 1024] = [MaybeUninit::uninit(); 3
 struct FlasherState {
     inited: bool,
     saved_cpu_state: CpuSaveState,
+    clock_boosted: bool,
     decompressor: Decompressor,
+    guard: FlashGuard,
+    preserve_partitions: bool,
+    kept_partitions: KeptPartitions,
+    skip_blank_erases: bool,
+    header_patch: HeaderPatch,
     read_buffer: [u8; 256],
 }
 
 static mut STATE: FlasherState = FlasherState {
     inited: false,
     saved_cpu_state: CpuSaveState::new(),
+    clock_boosted: false,
     decompressor: Decompressor::new(),
+    guard: FlashGuard::new(),
+    preserve_partitions: false,
+    kept_partitions: KeptPartitions::new(),
+    skip_blank_erases: false,
+    header_patch: HeaderPatch::new(),
     read_buffer: [0; 256],
 };
 
@@ -91,11 +120,15 @@ fn state() -> Option<&'static mut FlasherState> {
     }
 }
 
+/// `STATE` lives in .bss, so `init_bss` has already left the decompressor without a stream.
 fn init_state() -> &'static mut FlasherState {
     #[allow(static_mut_refs)]
     let state = unsafe { &mut STATE };
 
-    state.decompressor = Decompressor::new();
+    state.clock_boosted = false;
+    state.preserve_partitions = false;
+    state.skip_blank_erases = false;
+    state.header_patch.clear();
     state.inited = true;
 
     state
@@ -119,14 +152,19 @@ fn init_bss() {
 
 /// Setup the device for the flashing process.
 #[no_mangle]
-pub unsafe extern "C" fn Init_impl(_adr: u32, _clk: u32, _fnc: u32) -> i32 {
+pub unsafe extern "C" fn Init_impl(_adr: u32, _clk: u32, fnc: u32) -> i32 {
     init_bss();
-    dprintln!("INIT");
+    dprintln!("INIT {}", fnc);
 
     rom::init_rom_data();
 
     let state = init_state();
-    state.saved_cpu_state.set_max_cpu_clock();
+    // Verifying is bound by flash reads, leave the clocks alone.
+    if fnc != FNC_VERIFY {
+        state.saved_cpu_state.set_max_cpu_clock();
+        state.clock_boosted = true;
+    }
+    state.guard.init();
 
     flash::attach()
 }
@@ -136,18 +174,107 @@ pub unsafe extern "C" fn Init_impl(_adr: u32, _clk: u32, _fnc: u32) -> i32 {
 /// Returns 0 on success, 1 on failure.
 #[no_mangle]
 pub unsafe extern "C" fn EraseSector_impl(adr: u32) -> i32 {
-    if state().is_none() {
+    let Some(state) = state() else {
         return ERROR_BASE_INTERNAL - 1;
     };
-    flash::erase_block(adr)
+
+    let block_start = adr - adr % properties::FLASH_BLOCK_SIZE;
+
+    // Skipping the protected sectors would leave data the host expects to be erased.
+    if state
+        .guard
+        .is_protected(block_start, properties::FLASH_BLOCK_SIZE)
+    {
+        dprintln!("ERROR block {} has protected sectors", block_start);
+        return ERROR_BASE_INTERNAL - 16;
+    }
+
+    // Reading a block is much faster than erasing it, and doesn't wear the flash.
+    if state.skip_blank_erases
+        && flash::find_non_blank(block_start, properties::FLASH_BLOCK_SIZE, 0xFF) == Ok(None)
+    {
+        return 0;
+    }
+
+    erase_unprotected(&state.guard, None, block_start)
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn EraseChip_impl() -> i32 {
-    if state().is_none() {
+    let Some(state) = state() else {
         return ERROR_BASE_INTERNAL - 1;
     };
-    flash::erase_chip()
+
+    let kept = if state.preserve_partitions {
+        let status = state.kept_partitions.load();
+        if status != 0 {
+            return status;
+        }
+
+        Some(&state.kept_partitions)
+    } else {
+        None
+    };
+
+    if !state.guard.has_protected_ranges() && kept.is_none_or(|kept| kept.is_empty()) {
+        let status = state.guard.check(0, properties::MAX_FLASH_SIZE);
+        if status != 0 {
+            return status;
+        }
+
+        return flash::erase_chip();
+    }
+
+    // Erase around the protected ranges and kept partitions
+    let flash_size = match flash::get_flash_size() {
+        size if size > 0 => size as u32,
+        _ => properties::MAX_FLASH_SIZE,
+    };
+    for block_start in (0..flash_size).step_by(properties::FLASH_BLOCK_SIZE as usize) {
+        let status = erase_unprotected(&state.guard, kept, block_start);
+        if status != 0 {
+            return status;
+        }
+    }
+
+    0
+}
+
+/// Erases a block, skipping the sectors that are in a protected range or a kept partition.
+fn erase_unprotected(guard: &FlashGuard, kept: Option<&KeptPartitions>, block_start: u32) -> i32 {
+    const BLOCK_SIZE: u32 = properties::FLASH_BLOCK_SIZE;
+    const SECTOR_SIZE: u32 = properties::FLASH_SECTOR_SIZE;
+
+    let skip = |address: u32, len: u32| {
+        guard.is_protected(address, len) || kept.is_some_and(|kept| kept.overlaps(address, len))
+    };
+
+    if !skip(block_start, BLOCK_SIZE) {
+        let status = guard.check(block_start, BLOCK_SIZE);
+        if status != 0 {
+            return status;
+        }
+
+        return flash::erase_block(block_start);
+    }
+
+    for sector in (block_start..block_start + BLOCK_SIZE).step_by(SECTOR_SIZE as usize) {
+        if skip(sector, SECTOR_SIZE) {
+            continue;
+        }
+
+        let status = guard.check(sector, SECTOR_SIZE);
+        if status != 0 {
+            return status;
+        }
+
+        let status = flash::erase_sector(sector);
+        if status != 0 {
+            return status;
+        }
+    }
+
+    0
 }
 
 #[no_mangle]
@@ -165,7 +292,13 @@ pub unsafe extern "C" fn ProgramPage_impl(adr: u32, sz: u32, buf: *const u8) ->
 
     let input = core::slice::from_raw_parts(buf, sz as usize);
 
-    state.decompressor.program(adr, input)
+    state.decompressor.program(
+        adr,
+        input,
+        &state.guard,
+        &mut state.header_patch,
+        &mut state.read_buffer,
+    )
 }
 
 #[no_mangle]
@@ -183,7 +316,9 @@ pub unsafe extern "C" fn Verify_impl(adr: u32, sz: u32, buf: *const u8) -> i32 {
 
     let input = core::slice::from_raw_parts(buf, sz as usize);
 
-    state.decompressor.verify(adr, input)
+    state
+        .decompressor
+        .verify(adr, input, &mut state.header_patch)
 }
 
 #[no_mangle]
@@ -203,43 +338,104 @@ pub unsafe extern "C" fn ReadFlash_impl(adr: u32, sz: u32, buf: *mut u8) -> i32
     crate::flash::read_flash(adr, buf)
 }
 
+/// Computes the SHA-256 digest of `sz` bytes of flash at `adr` into the 32 bytes at `buf`. On the
+/// ESP32, `sz` has to be a multiple of 4.
 #[no_mangle]
-pub unsafe extern "C" fn BlankCheck_impl(adr: u32, sz: u32, pat: u8) -> i32 {
+pub unsafe extern "C" fn Sha256Digest_impl(adr: u32, sz: u32, buf: *mut u8) -> i32 {
     let Some(state) = state() else {
         return ERROR_BASE_INTERNAL - 1;
     };
 
-    for i in (0..sz).step_by(state.read_buffer.len()) {
-        if crate::flash::read_flash(adr + i as u32, &mut state.read_buffer) != 0 {
+    if (buf as u32) % 4 != 0 {
+        dprintln!("ERROR buf not word aligned");
+        return ERROR_BASE_INTERNAL - 5;
+    }
+
+    // The ESP32's ROM only hashes whole words.
+    if cfg!(feature = "esp32") && sz % 4 != 0 {
+        dprintln!("ERROR size not word aligned");
+        return ERROR_BASE_INTERNAL - 5;
+    }
+
+    dprintln!("SHA256 {} bytes @ {}", sz, adr);
+
+    let mut sha = sha::Sha256::new();
+    for offset in (0..sz).step_by(state.read_buffer.len()) {
+        let len = (sz - offset).min(state.read_buffer.len() as u32);
+        let chunk = state.read_buffer.get_mut(..len as usize).unwrap_or(&mut []);
+        if crate::flash::read_flash(adr + offset, chunk) != 0 {
             return ERROR_BASE_INTERNAL - 2;
         }
-        let mut idx = 0;
-        while idx < state.read_buffer.len() {
-            if state.read_buffer[idx] != pat {
-                return ERROR_BASE_INTERNAL - 3;
-            }
-            idx += 1;
-        }
+        sha.update(chunk);
     }
 
+    sha.finish(&mut *buf.cast::<MaybeUninit<[u8; 32]>>());
+
     0
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn UnInit_impl(fnc: u32) -> i32 {
+pub unsafe extern "C" fn BlankCheck_impl(adr: u32, sz: u32, pat: u8) -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    match find_non_blank(adr, sz, pat) {
+        Ok(None) => 0,
+        Ok(Some(address)) => {
+            dprintln!("NOT BLANK @ {}", address);
+            ERROR_BASE_INTERNAL - 3
+        }
+        Err(status) => status,
+    }
+}
+
+/// Returns the first address in `sz` bytes at `adr` that doesn't hold `pat`, or `adr + sz` if they
+/// all do.
+#[no_mangle]
+pub unsafe extern "C" fn FindNonBlank_impl(adr: u32, sz: u32, pat: u8) -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("FIND NON BLANK {} bytes @ {}", sz, adr);
+
+    match find_non_blank(adr, sz, pat) {
+        Ok(address) => address.unwrap_or(adr + sz) as i32,
+        Err(status) => status,
+    }
+}
+
+fn find_non_blank(adr: u32, sz: u32, pat: u8) -> Result<Option<u32>, i32> {
+    flash::find_non_blank(adr, sz, pat).map_err(|_| ERROR_BASE_INTERNAL - 2)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn UnInit_impl(_fnc: u32) -> i32 {
     let Some(state) = state() else {
         return ERROR_BASE_INTERNAL - 1;
     };
 
-    state.saved_cpu_state.restore();
+    let incomplete = state.decompressor.in_progress();
+
+    if state.clock_boosted {
+        state.saved_cpu_state.restore();
+    }
     state.inited = false;
 
-    if fnc == 2 {
-        // The flash ROM functions don't wait for the end of the last operation.
-        flash::wait_for_idle()
-    } else {
-        0
+    // Neither the ROM functions nor the native driver wait for the end of the last operation.
+    let status = flash::wait_for_idle();
+    if status != 0 {
+        return status;
     }
+
+    if incomplete {
+        // The rest of the stream never arrived, what we have can't be a complete image.
+        dprintln!("ERROR compressed stream incomplete");
+        return ERROR_BASE_INTERNAL - 13;
+    }
+
+    0
 }
 
 #[no_mangle]
@@ -251,12 +447,243 @@ pub unsafe extern "C" fn FlashSize_impl() -> i32 {
     flash::get_flash_size()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn ReadEfuse_impl(block: u32, sz: u32, buf: *mut u8) -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("READ EFUSE {} bytes @ block {}", sz, block);
+
+    let buf = core::slice::from_raw_parts_mut(buf, sz as usize);
+    if efuse::read_block(block, buf) != 0 {
+        return ERROR_BASE_INTERNAL - 6;
+    }
+
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn UnlockBootloader_impl() -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("UNLOCK BOOTLOADER");
+
+    state.guard.unlock_bootloader();
+
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn SetProtectedRanges_impl(count: u32, buf: *const u32) -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    if (buf as u32) % 4 != 0 {
+        dprintln!("ERROR buf not word aligned");
+        return ERROR_BASE_INTERNAL - 5;
+    }
+
+    dprintln!("SET PROTECTED RANGES {}", count);
+
+    if count as usize > guard::MAX_PROTECTED_RANGES {
+        return ERROR_BASE_INTERNAL - 17;
+    }
+
+    let ranges = core::slice::from_raw_parts(buf, count as usize * 2);
+    state.guard.set_protected_ranges(ranges)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ChipInfo_impl(buf: *mut u8) -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    if (buf as u32) % 4 != 0 {
+        dprintln!("ERROR buf not word aligned");
+        return ERROR_BASE_INTERNAL - 5;
+    }
+
+    dprintln!("CHIP INFO");
+
+    let info = &mut *buf.cast::<chip_info::ChipInfo>();
+    info.fill();
+
+    0
+}
+
+/// Makes `EraseChip` keep the NVS, NVS keys, PHY calibration and core dump partitions for the rest
+/// of the session.
+#[no_mangle]
+pub unsafe extern "C" fn PreservePartitions_impl() -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("PRESERVE PARTITIONS");
+
+    state.preserve_partitions = true;
+
+    0
+}
+
+/// Makes erases skip the blocks that already read as erased, for the rest of the session.
+#[no_mangle]
+pub unsafe extern "C" fn SkipBlankErases_impl() -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("SKIP BLANK ERASES");
+
+    state.skip_blank_erases = true;
+
+    0
+}
+
+/// Checks the start of an uncompressed image that is about to be written at `adr`, `sz` bytes at
+/// `buf`, like `ProgramPage` does once it has decompressed that far. `ProgramPage` only gets the
+/// image after `EraseSector` has erased what it replaces, so hosts call this first to keep the old
+/// image if the new one is for another chip or revision. Needs at least `image::HEAD_LEN` bytes
+/// to check an app's secure version.
+#[no_mangle]
+pub unsafe extern "C" fn CheckImage_impl(adr: u32, sz: u32, buf: *const u8) -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("CHECK IMAGE {} bytes @ {}", sz, adr);
+
+    let data = core::slice::from_raw_parts(buf, sz as usize);
+
+    image::check_head(adr, data)
+}
+
+/// Makes the pages of each compressed stream come at consecutive addresses, starting at the
+/// stream's destination address, and lets a page go on with further streams after one ends, each
+/// starting with its destination address and length. Lasts until the next `Init`.
+#[no_mangle]
+pub unsafe extern "C" fn SequencedStreams_impl() -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("SEQUENCED STREAMS");
+
+    state.decompressor.set_sequenced();
+
+    0
+}
+
+/// Makes flash operations drive SPI1 directly instead of calling the ROM functions, for the rest of
+/// the session, so that decompressing overlaps with programming. Fails on the ESP32, with octal
+/// flash, and if the driver can't be checked against the ROM because the bootloader is blank.
+#[no_mangle]
+pub unsafe extern "C" fn UseNativeFlash_impl() -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("USE NATIVE FLASH");
+
+    if !flash::use_native() {
+        dprintln!("ERROR native flash driver can't be used");
+        return ERROR_BASE_INTERNAL - 15;
+    }
+
+    0
+}
+
+/// Makes the bootloader start the `ota_<slot>` app partition next, by rewriting `otadata`.
+#[no_mangle]
+pub unsafe extern "C" fn SelectOtaSlot_impl(slot: u32) -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("SELECT OTA SLOT {}", slot);
+
+    ota::select_boot_slot(&state.guard, slot)
+}
+
+/// Rewrites the flash mode, size and frequency in the bootloader image header as it's written.
+/// `u32::MAX` keeps the image's value, a `size` of 0 uses the detected flash size. Needs to be set
+/// for verify sessions as well. Fails if secure boot is enabled, and signed bootloaders are
+/// written unchanged and reported.
+#[no_mangle]
+pub unsafe extern "C" fn SetFlashParams_impl(mode: u32, size: u32, freq: u32) -> i32 {
+    let Some(state) = state() else {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    dprintln!("SET FLASH PARAMS {} {} {}", mode, size, freq);
+
+    state.header_patch.configure(mode, size, freq)
+}
+
+/// Validates the partition table and copies up to `sz / 32` entries into `buf`. Returns the number
+/// of partitions in the table.
+#[no_mangle]
+pub unsafe extern "C" fn ReadPartitions_impl(sz: u32, buf: *mut u8) -> i32 {
+    if state().is_none() {
+        return ERROR_BASE_INTERNAL - 1;
+    };
+
+    if (buf as u32) % 4 != 0 {
+        dprintln!("ERROR buf not word aligned");
+        return ERROR_BASE_INTERNAL - 5;
+    }
+
+    dprintln!("READ PARTITIONS");
+
+    let table = match partition::PartitionTable::read() {
+        Ok(table) => table,
+        Err(status) => return status,
+    };
+
+    let count = sz as usize / core::mem::size_of::<partition::Partition>();
+    let partitions =
+        core::slice::from_raw_parts_mut(buf.cast::<MaybeUninit<partition::Partition>>(), count);
+    for (index, partition) in (0..table.len()).zip(partitions.iter_mut()) {
+        if let Err(status) = table.get(index, partition) {
+            return status;
+        }
+    }
+
+    table.len() as i32
+}
+
+/// What is done with the decompressed streams.
+trait StreamHandler {
+    /// Gets the first `image::HEAD_LEN` bytes of each stream, or all of it if it's shorter, before
+    /// any of it goes to `process`.
+    fn check_head(&mut self, image_start: u32, data: &[u8]) -> i32;
+
+    /// Gets the decompressed data, in order.
+    fn process(&mut self, address: u32, data: &[u8]) -> i32;
+
+    /// Called with the start address of each stream that ends.
+    fn finished(&mut self, image_start: u32) -> i32;
+}
+
 pub struct Decompressor {
     decompressor: TinflDecompressor,
     output: OutBuffer,
     image_start: Option<u32>,
     offset: u32,
+    /// Whether the start of the stream has been passed to `StreamHandler::check_head`.
+    head_checked: bool,
     remaining_compressed: usize,
+    /// Compressed address of the last page, and of the page that would follow it.
+    page_address: u32,
+    next_page_address: u32,
+    /// Whether the host sends the pages of a stream at consecutive addresses and may pack several
+    /// streams into a page. See `SequencedStreams`.
+    sequenced: bool,
 }
 
 impl Decompressor {
@@ -264,8 +691,12 @@ impl Decompressor {
         Self {
             image_start: None,
             offset: 0,
+            head_checked: false,
             output: OutBuffer::new(),
             remaining_compressed: 0,
+            page_address: 0,
+            next_page_address: 0,
+            sequenced: false,
             decompressor: TinflDecompressor::new(),
         }
     }
@@ -273,6 +704,7 @@ impl Decompressor {
     fn reinit(&mut self, address: u32, compressed: u32) {
         self.image_start = Some(address);
         self.offset = 0;
+        self.head_checked = false;
 
         self.remaining_compressed = compressed as usize;
 
@@ -280,37 +712,58 @@ impl Decompressor {
         self.output.take(|_| {});
     }
 
-    fn decompress(&mut self, input: &[u8], process: fn(u32, &[u8]) -> i32) -> i32 {
+    /// Whether a stream has been started but not all of it has been received and written out.
+    pub fn in_progress(&self) -> bool {
+        self.remaining_compressed > 0 || !self.output.is_empty()
+    }
+
+    /// Decompresses the part of `input` that belongs to the current stream, and leaves the rest in
+    /// `input`.
+    fn decompress(&mut self, input: &mut &[u8], handler: &mut dyn StreamHandler) -> i32 {
         if self.remaining_compressed == 0 {
             return ERROR_BASE_INTERNAL - 3;
         }
 
-        // We may have to cut off some padding bytes.
+        // The stream may end before the input does.
         let chunk_len = self.remaining_compressed.min(input.len());
         self.remaining_compressed -= chunk_len;
 
         // Signal tinfl_decompress() that this is the last chunk
         let last = self.remaining_compressed == 0;
 
-        // Iterate through all the input
-        let mut input = &input[..chunk_len];
+        // Iterate through all the input. The last chunk keeps going after its input is consumed:
+        // tinfl may still have output to flush, and it only checks the Adler-32 checksum at the
+        // end of the zlib stream once everything before it has been written out.
+        let (chunk, rest) = input.split_at(chunk_len);
+        *input = rest;
+        let mut input = chunk;
         let mut status = TINFL_STATUS_NEEDS_MORE_INPUT as i32;
-        while !input.is_empty() && status > TINFL_STATUS_DONE as i32 {
+        while (!input.is_empty() || last) && status > TINFL_STATUS_DONE as i32 {
             status = self
                 .decompressor
                 .decompress(&mut input, &mut self.output, last);
 
+            let head_status = self.check_head(status, handler);
+            if head_status != 0 {
+                return head_status;
+            }
+
             if status == TINFL_STATUS_DONE as i32 || self.output.full() {
                 // We're either finished or the decompressor can't continue
                 // until we flush the buffer.
-                let flush_status = self.flush(process);
+                let flush_status = self.flush(handler);
 
                 if flush_status < 0 {
-                    return ERROR_BASE_FLASH + flush_status;
+                    // The handlers already return loader error codes.
+                    return flush_status;
                 }
             }
         }
 
+        if status == TINFL_STATUS_ADLER32_MISMATCH as i32 {
+            dprintln!("ERROR Adler-32 mismatch, the compressed data was corrupted");
+        }
+
         if status < TINFL_STATUS_DONE as i32 {
             ERROR_BASE_TINFL + status
         } else {
@@ -318,7 +771,20 @@ impl Decompressor {
         }
     }
 
-    pub fn flush(&mut self, process: fn(address: u32, data: &[u8]) -> i32) -> i32 {
+    /// Passes the start of the stream to the handler once `image::HEAD_LEN` bytes of it have been
+    /// decompressed, or before it's flushed if it's shorter, so that nothing of it is written if
+    /// it's refused.
+    fn check_head(&mut self, status: i32, handler: &mut dyn StreamHandler) -> i32 {
+        let flushing = status == TINFL_STATUS_DONE as i32 || self.output.full();
+        if self.head_checked || (self.output.len() < image::HEAD_LEN && !flushing) {
+            return 0;
+        }
+        self.head_checked = true;
+
+        handler.check_head(self.image_start.unwrap_or(0), self.output.data())
+    }
+
+    fn flush(&mut self, handler: &mut dyn StreamHandler) -> i32 {
         let mut offset = self.offset;
         let address = self.image_start.unwrap_or(0) + offset;
 
@@ -326,7 +792,7 @@ impl Decompressor {
         let status = self.output.take(|data| {
             offset += data.len() as u32;
 
-            process(address, data)
+            handler.process(address, data)
         });
 
         self.offset = offset;
@@ -334,43 +800,98 @@ impl Decompressor {
         status
     }
 
+    /// Expects the pages of each stream at consecutive addresses, and allows several streams in a
+    /// page, until the next `Init`.
+    pub fn set_sequenced(&mut self) {
+        self.sequenced = true;
+    }
+
+    /// Decompresses a page of compressed data. The first page of a stream starts with the length
+    /// of the stream, the other pages repeat the address of the previous one or follow it. The
+    /// rest of the page after the end of a stream is padding.
+    ///
+    /// Sequenced streams only accept the page right after the previous one, so that a skipped,
+    /// repeated or reordered page is caught. A stream may end before the page does, followed by
+    /// another stream that starts with the address to decompress it to and its length, or by
+    /// padding.
     fn handle_compressed(
         &mut self,
         address: u32,
         mut data: &[u8],
-        process: fn(u32, &[u8]) -> i32,
+        handler: &mut dyn StreamHandler,
     ) -> i32 {
-        if self.image_start != Some(address) {
-            if data.len() < 4 {
+        let page_len = data.len() as u32;
+
+        if self.remaining_compressed > 0 {
+            let repeated = !self.sequenced && address == self.page_address;
+            if address != self.next_page_address && !repeated {
+                dprintln!(
+                    "ERROR stream continues @ {}, got page @ {}",
+                    self.next_page_address,
+                    address
+                );
+                return ERROR_BASE_INTERNAL - 12;
+            }
+        } else {
+            let Some(compressed_length) = take_u32(&mut data) else {
                 // We don't have enough bytes to read the length
                 return ERROR_BASE_INTERNAL - 4;
+            };
+
+            self.reinit(address, compressed_length);
+        }
+
+        self.page_address = address;
+        self.next_page_address = address + page_len;
+
+        loop {
+            let status = self.decompress(&mut data, handler);
+            if status != 0 || self.remaining_compressed > 0 {
+                return status;
             }
 
-            // Image length is prepended to the first chunk, cut it off.
-            let (length_bytes, remaining) = data.split_at(4);
-            data = remaining;
+            let status = handler.finished(self.image_start.unwrap_or(0));
+            if status != 0 || !self.sequenced {
+                return status;
+            }
 
-            let compressed_length = u32::from_le_bytes([
-                length_bytes[0],
-                length_bytes[1],
-                length_bytes[2],
-                length_bytes[3],
-            ]);
+            // Erased or zeroed padding can't be the header of another stream.
+            let (Some(stream_address), Some(compressed_length)) =
+                (take_u32(&mut data), take_u32(&mut data))
+            else {
+                return 0;
+            };
+            if stream_address == u32::MAX || compressed_length == 0 {
+                return 0;
+            }
 
-            self.reinit(address, compressed_length);
+            self.reinit(stream_address, compressed_length);
         }
-        self.decompress(data, process)
     }
 
-    pub fn program(&mut self, address: u32, data: &[u8]) -> i32 {
-        self.handle_compressed(address, data, write_to_flash)
+    pub fn program(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        guard: &FlashGuard,
+        header_patch: &mut HeaderPatch,
+        read_buffer: &mut [u8; 256],
+    ) -> i32 {
+        let mut programmer = Programmer {
+            guard,
+            header_patch,
+            read_buffer,
+        };
+
+        self.handle_compressed(address, data, &mut programmer)
     }
 
-    pub fn verify(&mut self, address: u32, data: &[u8]) -> i32 {
+    pub fn verify(&mut self, address: u32, data: &[u8], header_patch: &mut HeaderPatch) -> i32 {
         // We're supposed to return the address up to which we've verified.
         // However, we process compressed data and the caller expects us to respond in terms of
         // compressed offsets, so we don't actually know where comparison fails.
-        let status = if self.handle_compressed(address, data, verify_flash) == 0 {
+        let status = self.handle_compressed(address, data, &mut Verifier { header_patch });
+        let status = if status == 0 {
             address + data.len() as u32
         } else {
             address
@@ -380,11 +901,80 @@ impl Decompressor {
     }
 }
 
-fn write_to_flash(address: u32, data: &[u8]) -> i32 {
+struct Programmer<'a> {
+    guard: &'a FlashGuard,
+    header_patch: &'a mut HeaderPatch,
+    read_buffer: &'a mut [u8; 256],
+}
+
+impl StreamHandler for Programmer<'_> {
+    /// Refuses images for another chip before writing any of them.
+    fn check_head(&mut self, image_start: u32, data: &[u8]) -> i32 {
+        image::check_head(image_start, data)
+    }
+
+    fn process(&mut self, address: u32, data: &[u8]) -> i32 {
+        let guard = self.guard;
+        self.header_patch
+            .apply(address, data, &mut |address, data| {
+                write_to_flash(guard, address, data)
+            })
+    }
+
+    /// Checks complete bootloader and app images while we still can report it to the host.
+    fn finished(&mut self, image_start: u32) -> i32 {
+        let guard = self.guard;
+        let status = self
+            .header_patch
+            .finish(&mut |address, data| write_to_flash(guard, address, data));
+        if status != 0 {
+            return status;
+        }
+
+        if image::expected_at(image_start) {
+            image::check_written(image_start, self.read_buffer)
+        } else {
+            0
+        }
+    }
+}
+
+struct Verifier<'a> {
+    header_patch: &'a mut HeaderPatch,
+}
+
+impl StreamHandler for Verifier<'_> {
+    fn check_head(&mut self, _image_start: u32, _data: &[u8]) -> i32 {
+        0
+    }
+
+    fn process(&mut self, address: u32, data: &[u8]) -> i32 {
+        self.header_patch.apply(address, data, &mut verify_flash)
+    }
+
+    fn finished(&mut self, _image_start: u32) -> i32 {
+        self.header_patch.finish(&mut verify_flash)
+    }
+}
+
+fn take_u32(data: &mut &[u8]) -> Option<u32> {
+    let (bytes, rest) = data.split_first_chunk::<4>()?;
+    *data = rest;
+
+    Some(u32::from_le_bytes(*bytes))
+}
+
+fn write_to_flash(guard: &FlashGuard, address: u32, data: &[u8]) -> i32 {
+    let status = guard.check(address, data.len() as u32);
+    if status != 0 {
+        return status;
+    }
+
     let status = crate::flash::write_flash(address, data);
 
-    if status < 0 {
-        return ERROR_BASE_FLASH + status;
+    // The ROM and the native driver report failures as positive codes.
+    if status != 0 {
+        return ERROR_BASE_FLASH - status.wrapping_abs();
     }
 
     0
@@ -411,7 +1001,7 @@ fn verify_flash(mut address: u32, mut data: &[u8]) -> i32 {
         let readback_slice = &mut readback[..chunk_size];
 
         let status = crate::flash::read_flash(address, readback_slice);
-        if status < 0 {
+        if status != 0 {
             return -1;
         }
 
@@ -0,0 +1,152 @@
+//! Selects which OTA app slot the ESP-IDF bootloader starts, by updating the `otadata` partition.
+
+use core::mem::MaybeUninit;
+
+use crate::{
+    crc::crc32_le,
+    flash,
+    guard::FlashGuard,
+    partition::{PartitionTable, PARTITION_TYPE_APP, PARTITION_TYPE_DATA},
+    properties::FLASH_SECTOR_SIZE,
+    ERROR_BASE_INTERNAL,
+};
+
+const APP_SUBTYPE_OTA_0: u8 = 0x10;
+const MAX_OTA_SLOTS: u8 = 16;
+const DATA_SUBTYPE_OTA: u8 = 0x00;
+
+/// One of the two copies of ESP-IDF's `esp_ota_select_entry_t`, each at the start of a sector.
+#[repr(C)]
+struct OtaSelectEntry {
+    ota_seq: u32,
+    _seq_label: [u8; 20],
+    _ota_state: u32,
+    crc: u32,
+}
+
+impl OtaSelectEntry {
+    fn read(address: u32, buf: &mut MaybeUninit<Self>) -> Result<&Self, i32> {
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(
+                buf.as_mut_ptr().cast::<u8>(),
+                core::mem::size_of::<Self>(),
+            )
+        };
+
+        if flash::read_flash(address, bytes) != 0 {
+            return Err(ERROR_BASE_INTERNAL - 2);
+        }
+
+        // Every bit pattern is a valid `OtaSelectEntry`.
+        Ok(unsafe { buf.assume_init_ref() })
+    }
+
+    /// Returns the sequence number if the entry is in use and intact.
+    fn sequence(&self) -> Option<u32> {
+        let valid = self.ota_seq != u32::MAX && self.crc == seq_crc(self.ota_seq);
+        valid.then_some(self.ota_seq)
+    }
+}
+
+fn seq_crc(seq: u32) -> u32 {
+    crc32_le(u32::MAX, &seq.to_le_bytes())
+}
+
+/// Makes the bootloader start the `ota_<slot>` app partition on the next reset.
+pub fn select_boot_slot(guard: &FlashGuard, slot: u32) -> i32 {
+    let table = match PartitionTable::read() {
+        Ok(table) => table,
+        Err(status) => return status,
+    };
+
+    let mut otadata = None;
+    let mut slot_count = 0;
+    let mut slot_found = false;
+
+    let mut buf = MaybeUninit::uninit();
+    for index in 0..table.len() {
+        let partition = match table.get(index, &mut buf) {
+            Ok(partition) => partition,
+            Err(status) => return status,
+        };
+
+        match (partition.ty, partition.subtype) {
+            (PARTITION_TYPE_DATA, DATA_SUBTYPE_OTA) => otadata = Some(partition.offset),
+            (PARTITION_TYPE_APP, subtype)
+                if (APP_SUBTYPE_OTA_0..APP_SUBTYPE_OTA_0 + MAX_OTA_SLOTS).contains(&subtype) =>
+            {
+                slot_count += 1;
+                slot_found |= (subtype - APP_SUBTYPE_OTA_0) as u32 == slot;
+            }
+            _ => {}
+        }
+    }
+
+    let Some(otadata) = otadata else {
+        dprintln!("ERROR no otadata partition");
+        return ERROR_BASE_INTERNAL - 11;
+    };
+    if !slot_found {
+        dprintln!("ERROR no ota_{} partition", slot);
+        return ERROR_BASE_INTERNAL - 20;
+    }
+    // Like `esp_ota_set_boot_partition`, which can't reach slots past the number of OTA
+    // partitions either.
+    if slot >= slot_count {
+        dprintln!("ERROR ota_{} with only {} OTA partitions", slot, slot_count);
+        return ERROR_BASE_INTERNAL - 20;
+    }
+
+    // The bootloader picks the valid copy with the highest sequence number, so we overwrite the
+    // other one.
+    let mut sequences = [None; 2];
+    for (copy, sequence) in sequences.iter_mut().enumerate() {
+        let mut buf = MaybeUninit::uninit();
+        match OtaSelectEntry::read(otadata + copy as u32 * FLASH_SECTOR_SIZE, &mut buf) {
+            Ok(entry) => *sequence = entry.sequence(),
+            Err(status) => return status,
+        }
+    }
+
+    let (target, current) = match sequences {
+        [Some(a), Some(b)] if a >= b => (1, a),
+        [Some(_), Some(b)] => (0, b),
+        [Some(a), None] => (1, a),
+        [None, Some(b)] => (0, b),
+        [None, None] => (0, 0),
+    };
+
+    // The bootloader starts slot `(seq - 1) % slot_count`. Take the first such `seq` after
+    // `current`.
+    let seq = current + 1 + (slot + slot_count - current % slot_count) % slot_count;
+
+    dprintln!("SELECT ota_{} with seq {}", slot, seq);
+
+    let address = otadata + target * FLASH_SECTOR_SIZE;
+    let status = guard.check(address, FLASH_SECTOR_SIZE);
+    if status != 0 {
+        return status;
+    }
+
+    let status = flash::erase_sector(address);
+    if status != 0 {
+        return status;
+    }
+
+    // The label and state stay erased, which is `ESP_OTA_IMG_UNDEFINED` as written by
+    // `esp_ota_set_boot_partition` without rollback support. Only the sequence number and its CRC
+    // need to be written.
+    let status = write_word(address, seq);
+    if status != 0 {
+        return status;
+    }
+
+    let crc_offset = core::mem::offset_of!(OtaSelectEntry, crc) as u32;
+    write_word(address + crc_offset, seq_crc(seq))
+}
+
+fn write_word(address: u32, value: u32) -> i32 {
+    // Keeps the source word aligned for the ROM.
+    let bytes = unsafe { core::slice::from_raw_parts((&raw const value).cast::<u8>(), 4) };
+    flash::write_flash(address, bytes)
+}
@@ -0,0 +1,243 @@
+//! ESP-IDF partition table parser.
+
+use core::mem::MaybeUninit;
+
+use crate::{flash, ERROR_BASE_INTERNAL};
+
+pub const PARTITION_TABLE_OFFSET: u32 = 0x8000;
+const PARTITION_TABLE_MAX_LEN: u32 = 0xC00;
+const ENTRY_SIZE: u32 = core::mem::size_of::<Partition>() as u32;
+const MAX_ENTRIES: u32 = PARTITION_TABLE_MAX_LEN / ENTRY_SIZE;
+
+pub const PARTITION_TYPE_APP: u8 = 0x00;
+pub const PARTITION_TYPE_DATA: u8 = 0x01;
+
+const PARTITION_MAGIC: u16 = 0x50AA;
+const MD5_MAGIC: u16 = 0xEBEB;
+const END_MAGIC: u16 = 0xFFFF;
+
+/// A partition table entry, laid out like ESP-IDF's `esp_partition_info_t`. This is also what
+/// `ReadPartitions` hands to the host.
+#[repr(C)]
+pub struct Partition {
+    magic: u16,
+    pub ty: u8,
+    pub subtype: u8,
+    pub offset: u32,
+    pub size: u32,
+    /// NUL-padded.
+    pub label: [u8; 16],
+    pub flags: u32,
+}
+
+impl Partition {
+    pub fn end(&self) -> u32 {
+        self.offset.saturating_add(self.size)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts((self as *const Self).cast::<u8>(), ENTRY_SIZE as usize)
+        }
+    }
+}
+
+/// MD5 through the ROM. Laid out like the ROM's `struct MD5Context`, which is as large as the
+/// ESP32-C2's `mbedtls_md5_context`.
+#[repr(C)]
+struct Md5 {
+    state: [u32; 4],
+    bits: [u32; 2],
+    block: [u8; 64],
+}
+
+#[cfg(not(feature = "esp32c2"))]
+extern "C" {
+    fn MD5Init(ctx: *mut Md5);
+    fn MD5Update(ctx: *mut Md5, data: *const u8, len: u32);
+    fn MD5Final(digest: *mut u8, ctx: *mut Md5);
+}
+
+// The ESP32-C2 ROM has mbedtls' MD5 instead.
+#[cfg(feature = "esp32c2")]
+extern "C" {
+    fn mbedtls_md5_starts_ret(ctx: *mut Md5) -> i32;
+    fn mbedtls_md5_update_ret(ctx: *mut Md5, data: *const u8, len: u32) -> i32;
+    fn mbedtls_md5_finish_ret(ctx: *mut Md5, digest: *mut u8) -> i32;
+}
+
+impl Md5 {
+    fn new() -> Self {
+        let mut ctx = MaybeUninit::<Self>::uninit();
+        unsafe {
+            #[cfg(not(feature = "esp32c2"))]
+            MD5Init(ctx.as_mut_ptr());
+            #[cfg(feature = "esp32c2")]
+            mbedtls_md5_starts_ret(ctx.as_mut_ptr());
+
+            ctx.assume_init()
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        unsafe {
+            #[cfg(not(feature = "esp32c2"))]
+            MD5Update(self, data.as_ptr(), data.len() as u32);
+            #[cfg(feature = "esp32c2")]
+            mbedtls_md5_update_ret(self, data.as_ptr(), data.len() as u32);
+        }
+    }
+
+    fn finish(&mut self, digest: &mut [u8; 16]) {
+        unsafe {
+            #[cfg(not(feature = "esp32c2"))]
+            MD5Final(digest.as_mut_ptr(), self);
+            #[cfg(feature = "esp32c2")]
+            mbedtls_md5_finish_ret(self, digest.as_mut_ptr());
+        }
+    }
+}
+
+/// A partition table whose checksum has been verified.
+pub struct PartitionTable {
+    count: u32,
+}
+
+impl PartitionTable {
+    /// Reads and validates the partition table. Fails if the table is missing, malformed or its
+    /// MD5 entry does not match.
+    pub fn read() -> Result<Self, i32> {
+        let mut md5 = Md5::new();
+        let mut buf = MaybeUninit::uninit();
+
+        for index in 0..MAX_ENTRIES {
+            let entry = read_entry(index, &mut buf)?;
+
+            match entry.magic {
+                PARTITION_MAGIC => md5.update(entry.as_bytes()),
+                MD5_MAGIC => {
+                    let mut digest = [0; 16];
+                    md5.finish(&mut digest);
+
+                    let stored = entry.as_bytes().get(16..).unwrap_or(&[]);
+                    if !stored.iter().eq(digest.iter()) {
+                        return Err(ERROR_BASE_INTERNAL - 10);
+                    }
+
+                    return Self::new(index);
+                }
+                // Tables built without an MD5 entry end here.
+                END_MAGIC => return Self::new(index),
+                _ => return Err(ERROR_BASE_INTERNAL - 9),
+            }
+        }
+
+        Err(ERROR_BASE_INTERNAL - 9)
+    }
+
+    fn new(count: u32) -> Result<Self, i32> {
+        if count == 0 {
+            return Err(ERROR_BASE_INTERNAL - 9);
+        }
+
+        Ok(Self { count })
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    /// Reads the partition at `index` into `buf`.
+    pub fn get<'a>(
+        &self,
+        index: u32,
+        buf: &'a mut MaybeUninit<Partition>,
+    ) -> Result<&'a Partition, i32> {
+        if index >= self.count {
+            return Err(ERROR_BASE_INTERNAL - 18);
+        }
+
+        read_entry(index, buf)
+    }
+}
+
+fn read_entry(index: u32, buf: &mut MaybeUninit<Partition>) -> Result<&Partition, i32> {
+    let address = PARTITION_TABLE_OFFSET + index * ENTRY_SIZE;
+
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(buf.as_mut_ptr().cast::<u8>(), ENTRY_SIZE as usize)
+    };
+
+    if flash::read_flash(address, bytes) != 0 {
+        return Err(ERROR_BASE_INTERNAL - 2);
+    }
+
+    // Every bit pattern is a valid `Partition`.
+    Ok(unsafe { buf.assume_init_ref() })
+}
+
+/// Data partition subtypes a chip erase leaves alone when asked to preserve device data: PHY
+/// calibration, NVS (which factory data partitions use as well), core dumps and NVS keys. Matching
+/// on subtypes rather than labels also finds renamed partitions.
+const KEEP_SUBTYPES: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+const MAX_KEPT_PARTITIONS: usize = 8;
+
+/// Flash ranges of the data partitions with one of the `KEEP_SUBTYPES`.
+pub struct KeptPartitions {
+    ranges: [(u32, u32); MAX_KEPT_PARTITIONS],
+    count: usize,
+}
+
+impl KeptPartitions {
+    pub const fn new() -> Self {
+        Self {
+            ranges: [(0, 0); MAX_KEPT_PARTITIONS],
+            count: 0,
+        }
+    }
+
+    /// Looks up the kept partitions in the partition table.
+    pub fn load(&mut self) -> i32 {
+        self.count = 0;
+
+        let table = match PartitionTable::read() {
+            Ok(table) => table,
+            Err(status) => return status,
+        };
+
+        let mut buf = MaybeUninit::uninit();
+        for index in 0..table.len() {
+            let partition = match table.get(index, &mut buf) {
+                Ok(partition) => partition,
+                Err(status) => return status,
+            };
+
+            if partition.ty != PARTITION_TYPE_DATA || !KEEP_SUBTYPES.contains(&partition.subtype) {
+                continue;
+            }
+
+            let Some(range) = self.ranges.get_mut(self.count) else {
+                dprintln!("ERROR more than {} partitions to keep", MAX_KEPT_PARTITIONS);
+                return ERROR_BASE_INTERNAL - 19;
+            };
+            *range = (partition.offset, partition.end());
+            self.count += 1;
+
+            dprintln!("KEEP {} bytes @ {}", partition.size, partition.offset);
+        }
+
+        0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn overlaps(&self, address: u32, len: u32) -> bool {
+        let end = address.saturating_add(len);
+        self.ranges
+            .iter()
+            .take(self.count)
+            .any(|(start, stop)| address < *stop && *start < end)
+    }
+}
@@ -1,4 +1,4 @@
-pub use crate::chip::MAX_FLASH_SIZE;
+pub use crate::chip::{BOOTLOADER_OFFSET, MAX_FLASH_SIZE};
 
 // esptool uses 16k for the buffer
 pub const PAGE_SIZE: u32 = 0x4000;
@@ -7,6 +7,9 @@ pub const FLASH_BLOCK_SIZE: u32 = 65536;
 pub const FLASH_STATUS_MASK: u32 = 0xFFFF;
 pub const FLASH_SECTOR_SIZE: u32 = 4096;
 
+/// The second stage bootloader lives below the default partition table offset.
+pub const BOOTLOADER_REGION_END: u32 = 0x8000;
+
 #[allow(non_upper_case_globals)]
 #[no_mangle]
 #[used]
@@ -0,0 +1,107 @@
+//! Secure Boot V2 signature checks.
+
+#[cfg(any(
+    feature = "esp32s2",
+    feature = "esp32s3",
+    feature = "esp32c3",
+    feature = "esp32c5",
+    feature = "esp32c6",
+    feature = "esp32c61",
+    feature = "esp32h2",
+    feature = "esp32p4"
+))]
+pub fn verify_app(address: u32, image_len: u32, buf: &mut [u8]) -> i32 {
+    use core::mem::MaybeUninit;
+
+    use crate::{flash, properties::FLASH_SECTOR_SIZE, sha::Sha256, ERROR_BASE_IMAGE};
+
+    const SB_SUCCESS: u32 = 0x3A5A5AA5;
+
+    /// The signature sector that follows the image, `ets_secure_boot_signature_t`.
+    #[repr(C, align(4))]
+    struct Signature([u8; 4096]);
+
+    /// `ets_secure_boot_key_digests_t`, with room for the ROM versions that have a trailing
+    /// `allow_key_revoke` flag.
+    #[repr(C)]
+    struct KeyDigests {
+        key_digests: [*const u8; 3],
+        _allow_key_revoke: bool,
+    }
+
+    extern "C" {
+        fn ets_secure_boot_read_key_digests(trusted_keys: *mut KeyDigests) -> u32;
+        fn ets_secure_boot_verify_signature(
+            sig: *const Signature,
+            image_digest: *const u8,
+            trusted_keys: *const KeyDigests,
+            verified_digest: *mut u8,
+        ) -> u32;
+    }
+
+    // The signature sector starts at the next sector boundary, and covers everything before it.
+    let signed_len = image_len.next_multiple_of(FLASH_SECTOR_SIZE);
+
+    let mut sha = Sha256::new();
+    for offset in (0..signed_len).step_by(buf.len()) {
+        let len = (signed_len - offset).min(buf.len() as u32);
+        let chunk = buf.get_mut(..len as usize).unwrap_or(&mut []);
+        if flash::read_flash(address + offset, chunk) != 0 {
+            return ERROR_BASE_IMAGE - 5;
+        }
+        sha.update(chunk);
+    }
+    let mut digest = MaybeUninit::uninit();
+    let digest = sha.finish(&mut digest);
+
+    let mut signature = MaybeUninit::<Signature>::uninit();
+    let signature_bytes = unsafe {
+        core::slice::from_raw_parts_mut(
+            signature.as_mut_ptr().cast::<u8>(),
+            core::mem::size_of::<Signature>(),
+        )
+    };
+    if flash::read_flash(address + signed_len, signature_bytes) != 0 {
+        return ERROR_BASE_IMAGE - 5;
+    }
+
+    let mut keys = MaybeUninit::<KeyDigests>::uninit();
+    if unsafe { ets_secure_boot_read_key_digests(keys.as_mut_ptr()) } != 0 {
+        dprintln!("ERROR can't read secure boot key digests");
+        return ERROR_BASE_IMAGE - 8;
+    }
+
+    let mut verified = MaybeUninit::<[u8; 32]>::uninit();
+    let status = unsafe {
+        ets_secure_boot_verify_signature(
+            signature.as_ptr(),
+            digest.as_ptr(),
+            keys.as_ptr(),
+            verified.as_mut_ptr().cast(),
+        )
+    };
+
+    if status != SB_SUCCESS {
+        dprintln!("ERROR app signature is not valid");
+        return ERROR_BASE_IMAGE - 8;
+    }
+
+    // Like the bootloader, also compare the digest the ROM says it verified.
+    let verified = unsafe { verified.assume_init_ref() };
+    if !verified.iter().eq(digest.iter()) {
+        dprintln!("ERROR app signature is not valid");
+        return ERROR_BASE_IMAGE - 8;
+    }
+
+    0
+}
+
+#[cfg(any(feature = "esp32", feature = "esp32c2"))]
+pub fn verify_app(_address: u32, _image_len: u32, _buf: &mut [u8]) -> i32 {
+    // The ESP32 only has the ROM Secure Boot V2 functions from ECO3 on, and Secure Boot V1 app
+    // signatures are checked against a key built into the bootloader. The ESP32-C2 keeps a single
+    // ECDSA key digest in a layout this check doesn't read. The bootloader still checks the app.
+    dprintln!("WARNING app signature not checked on this chip");
+
+    0
+}
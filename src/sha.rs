@@ -0,0 +1,180 @@
+//! SHA-256 using the SHA peripheral through the ROM's `ets_sha_*` functions.
+//!
+//! There is a single peripheral, so only one digest can be computed at a time.
+
+#[cfg(any(
+    feature = "esp32s2",
+    feature = "esp32s3",
+    feature = "esp32c2",
+    feature = "esp32c3",
+    feature = "esp32c5",
+    feature = "esp32c6",
+    feature = "esp32c61",
+    feature = "esp32h2",
+    feature = "esp32p4"
+))]
+mod imp {
+    use core::mem::MaybeUninit;
+
+    const SHA2_256: u32 = 2;
+
+    #[repr(C)]
+    struct ShaCtx {
+        start: bool,
+        in_hardware: bool,
+        ty: u32,
+        state: [u32; 16],
+        buffer: [u8; 128],
+        total_bits: [u32; 4],
+    }
+
+    extern "C" {
+        fn ets_sha_enable();
+        fn ets_sha_disable();
+        fn ets_sha_init(ctx: *mut ShaCtx, ty: u32) -> i32;
+        fn ets_sha_update(ctx: *mut ShaCtx, input: *const u8, len: u32, update_ctx: bool);
+        fn ets_sha_finish(ctx: *mut ShaCtx, output: *mut u8) -> i32;
+    }
+
+    static mut CTX: MaybeUninit<ShaCtx> = MaybeUninit::uninit();
+
+    pub fn start() {
+        unsafe {
+            ets_sha_enable();
+            ets_sha_init((&raw mut CTX).cast(), SHA2_256);
+        }
+    }
+
+    pub fn update(data: &[u8]) {
+        unsafe {
+            ets_sha_update(
+                (&raw mut CTX).cast(),
+                data.as_ptr(),
+                data.len() as u32,
+                false,
+            )
+        };
+    }
+
+    pub fn finish(digest: *mut u8) {
+        unsafe {
+            ets_sha_finish((&raw mut CTX).cast(), digest);
+            ets_sha_disable();
+        }
+    }
+}
+
+#[cfg(feature = "esp32")]
+mod imp {
+    use core::mem::MaybeUninit;
+
+    const SHA2_256: u32 = 1;
+
+    #[repr(C)]
+    struct ShaCtx {
+        start: bool,
+        total_input_bits: [u32; 4],
+    }
+
+    extern "C" {
+        fn ets_sha_enable();
+        fn ets_sha_disable();
+        fn ets_sha_init(ctx: *mut ShaCtx);
+        fn ets_sha_update(ctx: *mut ShaCtx, ty: u32, input: *const u8, input_bits: u32);
+        fn ets_sha_finish(ctx: *mut ShaCtx, ty: u32, output: *mut u8);
+    }
+
+    /// The ROM only hashes whole words. Bytes that don't make one wait for the next update.
+    #[repr(C, align(4))]
+    struct Tail {
+        bytes: [u8; 4],
+        len: usize,
+    }
+
+    static mut CTX: MaybeUninit<ShaCtx> = MaybeUninit::uninit();
+    static mut TAIL: Tail = Tail {
+        bytes: [0; 4],
+        len: 0,
+    };
+
+    fn tail() -> &'static mut Tail {
+        #[allow(static_mut_refs)]
+        unsafe {
+            &mut TAIL
+        }
+    }
+
+    fn hash_words(data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let bits = data.len() as u32 * 8;
+        unsafe { ets_sha_update((&raw mut CTX).cast(), SHA2_256, data.as_ptr(), bits) };
+    }
+
+    pub fn start() {
+        tail().len = 0;
+        unsafe {
+            ets_sha_enable();
+            ets_sha_init((&raw mut CTX).cast());
+        }
+    }
+
+    pub fn update(mut data: &[u8]) {
+        let tail = tail();
+
+        if tail.len > 0 {
+            for byte in tail.bytes.iter_mut().skip(tail.len) {
+                let Some((first, rest)) = data.split_first() else {
+                    break;
+                };
+                *byte = *first;
+                tail.len += 1;
+                data = rest;
+            }
+            if tail.len < tail.bytes.len() {
+                return;
+            }
+
+            hash_words(&tail.bytes);
+            tail.len = 0;
+        }
+
+        let (words, rest) = data.split_at(data.len() & !3);
+        hash_words(words);
+
+        for (byte, rest) in tail.bytes.iter_mut().zip(rest) {
+            *byte = *rest;
+        }
+        tail.len = rest.len();
+    }
+
+    /// The total length hashed must be a multiple of 4 bytes.
+    pub fn finish(digest: *mut u8) {
+        unsafe {
+            ets_sha_finish((&raw mut CTX).cast(), SHA2_256, digest);
+            ets_sha_disable();
+        }
+    }
+}
+
+use core::mem::MaybeUninit;
+
+pub struct Sha256(());
+
+impl Sha256 {
+    pub fn new() -> Self {
+        imp::start();
+        Self(())
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        imp::update(data);
+    }
+
+    pub fn finish(self, digest: &mut MaybeUninit<[u8; 32]>) -> &[u8; 32] {
+        imp::finish(digest.as_mut_ptr().cast());
+        unsafe { digest.assume_init_ref() }
+    }
+}
@@ -23,7 +23,7 @@ extern "C" {
 type TinflStatus = i8;
 // const TINFL_STATUS_FAILED_CANNOT_MAKE_PROGRESS: TinflStatus = -4;
 // const TINFL_STATUS_BAD_PARAM: TinflStatus = -3;
-// const TINFL_STATUS_ADLER32_MISMATCH: TinflStatus = -2;
+pub const TINFL_STATUS_ADLER32_MISMATCH: TinflStatus = -2;
 // const TINFL_STATUS_FAILED: TinflStatus = -1;
 pub const TINFL_STATUS_DONE: TinflStatus = 0;
 pub const TINFL_STATUS_NEEDS_MORE_INPUT: TinflStatus = 1;
@@ -118,6 +118,22 @@ impl OutBuffer {
         self.space() == 0
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The decompressed data that hasn't been taken yet.
+    pub fn data(&self) -> &[u8] {
+        unsafe {
+            // self.len is always <= self.buffer.len()
+            self.buffer.assume_init_ref().get_unchecked(..self.len)
+        }
+    }
+
     pub fn take<R>(&mut self, out: impl FnOnce(&[u8]) -> R) -> R {
         let data = unsafe {
             // self.len is always <= self.buffer.len()